@@ -0,0 +1,219 @@
+use std::fs;
+use std::io::sink;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+
+use pcomp::comp_structs::{
+    dictionary::Dictionary,
+    header::Header,
+    options::{Mode, Options},
+};
+use pcomp::utility::{CHUNK_MAX_SIZE, ELEM_BYTES, MIN_OCCATIONS};
+use pcomp::input::Input;
+use pcomp::{compress, decompress, prediction};
+
+mod corpus;
+
+use corpus::Corpus;
+
+fn first_chunk(data: &[u8]) -> &[u8] {
+    &data[..data.len().min(CHUNK_MAX_SIZE as usize)]
+}
+
+fn chunk_dicts(chunk: &[u8]) -> Vec<Dictionary> {
+    (0..ELEM_BYTES as u64)
+        .map(|alignment| {
+            let mut dict = compress::generate_dict(chunk, alignment, ELEM_BYTES, MIN_OCCATIONS);
+            dict.coverage = chunk.len() as u64;
+            dict
+        })
+        .collect()
+}
+
+fn encode_chunk(chunk: &[u8]) -> Vec<u8> {
+    let mut dicts = chunk_dicts(chunk);
+    let mut out = vec![];
+
+    compress::compress_chunk(&mut dicts, chunk, &mut out, &Options::default()).unwrap();
+
+    out
+}
+
+fn corpus_file(corpus: &Corpus) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pcomp-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join(format!("{}.bin", corpus.name));
+    fs::write(&path, &corpus.data).unwrap();
+
+    path
+}
+
+/// Writes the corpus repeated to `size` bytes, to compare mapping a large input with reading it into memory.
+fn large_file(corpus: &Corpus, size: usize) -> PathBuf {
+    let small = corpus_file(corpus);
+    fs::remove_file(&small).unwrap();
+
+    let path = small.with_extension("large");
+    let data: Vec<u8> = corpus.data.iter().copied().cycle().take(size).collect();
+    fs::write(&path, data).unwrap();
+
+    path
+}
+
+fn round_trip(path: &Path, options: &Options) -> u64 {
+    let comp = compress::run(path, options).unwrap();
+    let comp_len = comp.metadata().unwrap().len();
+
+    let decomp = decompress::run(&comp).unwrap();
+    fs::remove_file(&comp).unwrap();
+    fs::remove_file(&decomp).unwrap();
+
+    comp_len
+}
+
+fn report_ratios(corpora: &[Corpus]) {
+    println!(
+        "\n{:<8} {:>10} {:>12} {:>8} {:>12} {:>8} {:>12} {:>8} {:>12} {:>8}",
+        "corpus", "bytes", "chunk", "ratio", "file", "ratio", "re-pair", "ratio", "bwt", "ratio"
+    );
+
+    let repair = Options { mode: Mode::RePair, ..Options::default() };
+    let bwt = Options { bwt: true, ..Options::default() };
+
+    for corpus in corpora {
+        let chunk = first_chunk(&corpus.data);
+        let chunk_len = encode_chunk(chunk).len();
+        let path = corpus_file(corpus);
+        let file_len = round_trip(&path, &Options::default());
+        let repair_len = round_trip(&path, &repair);
+        let bwt_len = round_trip(&path, &bwt);
+        fs::remove_file(&path).unwrap();
+
+        println!(
+            "{:<8} {:>10} {:>12} {:>8.3} {:>12} {:>8.3} {:>12} {:>8.3} {:>12} {:>8.3}",
+            corpus.name,
+            corpus.data.len(),
+            chunk_len,
+            chunk_len as f64 / chunk.len() as f64,
+            file_len,
+            file_len as f64 / corpus.data.len() as f64,
+            repair_len,
+            repair_len as f64 / corpus.data.len() as f64,
+            bwt_len,
+            bwt_len as f64 / corpus.data.len() as f64,
+        );
+    }
+
+    println!();
+}
+
+fn bench_codec(c: &mut Criterion) {
+    let corpora = corpus::all();
+    report_ratios(&corpora);
+
+    let mut group = c.benchmark_group("generate_dict");
+    for corpus in corpora.iter() {
+        let chunk = first_chunk(&corpus.data);
+        group.throughput(Throughput::Bytes(chunk.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(corpus.name), chunk, |b, chunk| {
+            b.iter(|| compress::generate_dict(chunk, 0, ELEM_BYTES, MIN_OCCATIONS))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("compress_chunk");
+    for corpus in corpora.iter() {
+        let chunk = first_chunk(&corpus.data);
+        let dicts = chunk_dicts(chunk);
+
+        group.throughput(Throughput::Bytes(chunk.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(corpus.name), chunk, |b, chunk| {
+            b.iter_batched(
+                || dicts.clone(),
+                |mut dicts| compress::compress_chunk(&mut dicts, chunk, &mut sink(), &Options::default()).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("compress_layer");
+    for corpus in corpora.iter() {
+        let path = corpus_file(corpus);
+
+        group.throughput(Throughput::Bytes(corpus.data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(corpus.name), &corpus.data, |b, data| {
+            b.iter_batched(
+                || compress::generate_dict_collection(data, &Options::default()),
+                |mut dicts| {
+                    let out = compress::compress_layer(&path, data, &mut dicts, &Options::default(), &[]).unwrap();
+                    fs::remove_file(out).unwrap();
+                },
+                BatchSize::LargeInput,
+            )
+        });
+
+        fs::remove_file(&path).unwrap();
+    }
+    group.finish();
+
+    let header = Header::new(1, ELEM_BYTES);
+    let mut group = c.benchmark_group("decompress_chunk");
+    for corpus in corpora.iter() {
+        let chunk = first_chunk(&corpus.data);
+        let encoded = encode_chunk(chunk);
+
+        group.throughput(Throughput::Bytes(chunk.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(corpus.name), &encoded, |b, encoded| {
+            b.iter(|| decompress::decompress_chunk(&mut sink(), encoded, &header, &mut vec![]).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("read_input");
+    for corpus in corpora.iter().filter(|corpus| corpus.name == "text" || corpus.name == "binary") {
+        let path = large_file(corpus, 32 << 20);
+
+        // both read the whole file once and scan it the way the layer loop does before encoding
+        group.throughput(Throughput::Bytes(32 << 20));
+        group.bench_with_input(BenchmarkId::new("mmap", corpus.name), &path, |b, path| {
+            b.iter(|| {
+                let input = Input::open(path).unwrap();
+                assert!(input.is_mapped());
+                prediction::layer_gain(&input, ELEM_BYTES, true)
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("buffered", corpus.name), &path, |b, path| {
+            b.iter(|| {
+                let input = Input::buffered(fs::File::open(path).unwrap()).unwrap();
+                prediction::layer_gain(&input, ELEM_BYTES, true)
+            })
+        });
+
+        fs::remove_file(&path).unwrap();
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("round_trip");
+    for corpus in corpora.iter() {
+        let path = corpus_file(corpus);
+
+        group.throughput(Throughput::Bytes(corpus.data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(corpus.name), &path, |b, path| {
+            b.iter(|| round_trip(path, &Options::default()))
+        });
+
+        fs::remove_file(&path).unwrap();
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10).measurement_time(Duration::from_secs(5));
+    targets = bench_codec
+}
+criterion_main!(benches);
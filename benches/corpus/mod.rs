@@ -0,0 +1,170 @@
+//! Synthetic corpora for the benchmarks, generated from a fixed seed so every run measures the same data.
+
+pub const CORPUS_SIZE: usize = 1 << 20;
+
+pub struct Corpus {
+    pub name: &'static str,
+    pub data: Vec<u8>,
+}
+
+pub fn all() -> Vec<Corpus> {
+    vec![
+        Corpus { name: "text", data: text(CORPUS_SIZE) },
+        Corpus { name: "json", data: json(CORPUS_SIZE) },
+        Corpus { name: "random", data: random(CORPUS_SIZE) },
+        Corpus { name: "zeros", data: vec![0u8; CORPUS_SIZE] },
+        Corpus { name: "binary", data: binary(CORPUS_SIZE) },
+        Corpus { name: "sparse", data: sparse(CORPUS_SIZE) },
+    ]
+}
+
+// xorshift64*, good enough for test data and free of dependencies
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, max: usize) -> usize {
+        (self.next() % max as u64) as usize
+    }
+
+    // picks low values far more often than high ones
+    fn skewed(&mut self, max: usize) -> usize {
+        let limit = 1 + self.below(max);
+        self.below(limit)
+    }
+
+    fn byte(&mut self) -> u8 {
+        (self.next() >> 56) as u8
+    }
+}
+
+fn words(rng: &mut Rng, count: usize) -> Vec<String> {
+    let letters = b"etaoinshrdlcumwfgypbvkjxqz";
+
+    (0..count)
+        .map(|_| {
+            let len = 2 + rng.below(8);
+            // skew towards common letters the way natural text does
+            (0..len).map(|_| letters[rng.skewed(letters.len())] as char).collect()
+        })
+        .collect()
+}
+
+pub fn text(size: usize) -> Vec<u8> {
+    let mut rng = Rng::new(1);
+    let words = words(&mut rng, 2000);
+    let mut out = String::with_capacity(size);
+
+    while out.len() < size {
+        let sentence = 4 + rng.below(14);
+        for i in 0..sentence {
+            // common words are picked far more often than rare ones
+            let word = &words[rng.skewed(words.len())];
+            if i == 0 {
+                out.push_str(&word[..1].to_uppercase());
+                out.push_str(&word[1..]);
+            } else {
+                out.push(' ');
+                out.push_str(word);
+            }
+        }
+        out.push_str(if rng.below(5) == 0 { ".\n" } else { ". " });
+    }
+
+    out.truncate(size);
+    out.into_bytes()
+}
+
+pub fn json(size: usize) -> Vec<u8> {
+    let mut rng = Rng::new(2);
+    let names = words(&mut rng, 300);
+    let mut out = String::from("[\n");
+    let mut id = 0;
+
+    while out.len() < size {
+        id += 1;
+        out.push_str(&format!(
+            "  {{\"id\": {}, \"name\": \"{}\", \"active\": {}, \"score\": {}.{}, \"tags\": [\"{}\", \"{}\"]}},\n",
+            id,
+            names[rng.below(names.len())],
+            rng.below(2) == 0,
+            rng.below(1000),
+            rng.below(100),
+            names[rng.below(20)],
+            names[rng.below(20)],
+        ));
+    }
+
+    out.truncate(size);
+    out.into_bytes()
+}
+
+pub fn random(size: usize) -> Vec<u8> {
+    let mut rng = Rng::new(3);
+    (0..size).map(|_| rng.byte()).collect()
+}
+
+/// Imitates x86-64 machine code: common prologues, moves, calls with relative targets and returns.
+pub fn binary(size: usize) -> Vec<u8> {
+    let mut rng = Rng::new(4);
+    let mut out = Vec::with_capacity(size);
+
+    while out.len() < size {
+        match rng.below(8) {
+            // push rbp; mov rbp, rsp
+            0 => out.extend(&[0x55, 0x48, 0x89, 0xe5]),
+            // call rel32
+            1 => {
+                out.push(0xe8);
+                out.extend(&(rng.below(1 << 16) as i32 - (1 << 15)).to_le_bytes());
+            }
+            // mov reg, [rbp - disp8]
+            2 | 3 => out.extend(&[0x48, 0x8b, 0x45 + 8 * rng.below(4) as u8, 0xf8 - 8 * rng.below(4) as u8]),
+            // mov eax, imm32
+            4 => {
+                out.push(0xb8);
+                out.extend(&(rng.below(256) as u32).to_le_bytes());
+            }
+            // pop rbp; ret, padded to 16 bytes
+            5 => {
+                out.extend(&[0x5d, 0xc3]);
+                while out.len() % 16 != 0 {
+                    out.push(0xcc);
+                }
+            }
+            // a few bytes of constant data
+            _ => (0..rng.below(8)).for_each(|_| out.push(rng.byte())),
+        }
+    }
+
+    out.truncate(size);
+    out
+}
+
+/// Mostly zeros with scattered values and short dense blocks, like a sparse disk image.
+pub fn sparse(size: usize) -> Vec<u8> {
+    let mut rng = Rng::new(5);
+    let mut out = vec![0u8; size];
+    let mut pos = 0;
+
+    while pos < size {
+        pos += rng.below(4096);
+        let len = if rng.below(10) == 0 { rng.below(512) } else { 1 };
+        for byte in out.iter_mut().skip(pos).take(len) {
+            *byte = rng.byte();
+        }
+        pos += len;
+    }
+
+    out
+}
@@ -0,0 +1,190 @@
+use std::io::{Error, ErrorKind, Result};
+
+/// Applies the Burrows-Wheeler transform followed by move-to-front to a block, returning the transformed bytes
+/// and the primary index, the row of the sorted rotations holding the end of the block.
+///
+/// Repeated contexts end up next to each other, so the transformed block is mostly made of a few small
+/// values, which repeat in pairs.
+pub fn encode(block: &[u8]) -> (Vec<u8>, usize) {
+    let (last_column, primary) = transform(block);
+    (move_to_front(&last_column), primary)
+}
+
+/// Reverts `encode` for the transformed bytes and primary index of a block.
+pub fn decode(data: &[u8], primary: usize) -> Result<Vec<u8>> {
+    inverse_transform(&undo_move_to_front(data), primary)
+}
+
+/// Sorts the suffixes of the block followed by an end marker smaller than any byte, and returns the byte
+/// before each suffix, leaving out the end marker, and the row the end marker was left out of.
+fn transform(block: &[u8]) -> (Vec<u8>, usize) {
+    let suffixes = suffix_array(block);
+
+    // the suffix holding only the end marker comes first, preceded by the last byte
+    let mut last_column = Vec::with_capacity(block.len());
+    let mut primary = 0;
+    last_column.extend(block.last());
+
+    for (row, suffix) in suffixes.iter().enumerate() {
+        match suffix {
+            0 => primary = row + 1,
+            _ => last_column.push(block[suffix - 1]),
+        }
+    }
+
+    (last_column, primary)
+}
+
+fn inverse_transform(last_column: &[u8], primary: usize) -> Result<Vec<u8>> {
+    let len = last_column.len();
+
+    if primary == 0 && len > 0 || primary > len {
+        return Err(Error::new(ErrorKind::InvalidData, "primary index out of range"));
+    }
+
+    // the symbol of each row with the end marker put back, as 0 below every byte
+    let symbol = |row: usize| match row.cmp(&primary) {
+        std::cmp::Ordering::Less => last_column[row] as usize + 1,
+        std::cmp::Ordering::Equal => 0,
+        std::cmp::Ordering::Greater => last_column[row - 1] as usize + 1,
+    };
+
+    // rows before the first row starting with each symbol
+    let mut starts = [0usize; 257];
+    for byte in last_column {
+        starts[*byte as usize + 1] += 1;
+    }
+
+    let mut total = 1;
+    for start in starts.iter_mut() {
+        let count = *start;
+        *start = total;
+        total += count;
+    }
+    starts[0] = 0;
+
+    // maps each row to the row of the suffix starting one byte earlier
+    let mut next_row = vec![0u32; len + 1];
+    for (row, next) in next_row.iter_mut().enumerate() {
+        let sym = symbol(row);
+        *next = starts[sym] as u32;
+        starts[sym] += 1;
+    }
+
+    let mut block = vec![0u8; len];
+    let mut row = 0;
+
+    // a damaged block can reach the end marker before the whole block is restored
+    for byte in block.iter_mut().rev() {
+        *byte = match symbol(row) {
+            0 => return Err(Error::new(ErrorKind::InvalidData, "transformed block is damaged")),
+            sym => (sym - 1) as u8,
+        };
+
+        row = next_row[row] as usize;
+    }
+
+    Ok(block)
+}
+
+/// Start positions of the suffixes in sorted order, where a suffix sorts before every longer suffix it is
+/// a prefix of. Sorts by prefix doubling, ranking the suffixes by twice as many bytes each round with two
+/// counting sorts, until every rank is distinct.
+fn suffix_array(block: &[u8]) -> Vec<usize> {
+    let len = block.len();
+    let mut suffixes: Vec<usize> = (0..len).collect();
+    let mut rank: Vec<usize> = block.iter().map(|byte| *byte as usize + 1).collect();
+    let mut ranks = 257;
+    let mut width = 1;
+
+    let mut sorted = vec![0usize; len];
+    let mut counts: Vec<usize> = vec![];
+
+    if len == 0 {
+        return suffixes;
+    }
+
+    loop {
+        // the rank of the bytes `width` on, 0 past the end of the block
+        let second = |suffix: usize| if suffix + width < len { rank[suffix + width] } else { 0 };
+
+        counting_sort(&suffixes, &mut sorted, &mut counts, ranks, second);
+        counting_sort(&sorted, &mut suffixes, &mut counts, ranks, |suffix| rank[suffix]);
+
+        let mut new_rank = vec![0usize; len];
+        let mut current = 1;
+        new_rank[suffixes[0]] = current;
+
+        for pair in suffixes.windows(2) {
+            if (rank[pair[0]], second(pair[0])) != (rank[pair[1]], second(pair[1])) {
+                current += 1;
+            }
+
+            new_rank[pair[1]] = current;
+        }
+
+        rank = new_rank;
+        ranks = current + 1;
+
+        if current == len {
+            break;
+        }
+
+        width *= 2;
+    }
+
+    suffixes
+}
+
+/// Stable sort of `input` into `output` by keys below `ranks`.
+fn counting_sort<F: Fn(usize) -> usize>(
+    input: &[usize],
+    output: &mut [usize],
+    counts: &mut Vec<usize>,
+    ranks: usize,
+    key: F,
+) {
+    counts.clear();
+    counts.resize(ranks + 1, 0);
+
+    for suffix in input {
+        counts[key(*suffix) + 1] += 1;
+    }
+
+    for rank in 1..counts.len() {
+        counts[rank] += counts[rank - 1];
+    }
+
+    for suffix in input {
+        let slot = &mut counts[key(*suffix)];
+        output[*slot] = *suffix;
+        *slot += 1;
+    }
+}
+
+/// Replaces each byte by its position in a list of all bytes, moving the byte to the front of the list.
+fn move_to_front(data: &[u8]) -> Vec<u8> {
+    let mut order: Vec<u8> = (0..=255).collect();
+
+    data.iter()
+        .map(|byte| {
+            let index = order.iter().position(|listed| listed == byte).unwrap();
+            order.copy_within(0..index, 1);
+            order[0] = *byte;
+            index as u8
+        })
+        .collect()
+}
+
+fn undo_move_to_front(data: &[u8]) -> Vec<u8> {
+    let mut order: Vec<u8> = (0..=255).collect();
+
+    data.iter()
+        .map(|index| {
+            let byte = order[*index as usize];
+            order.copy_within(0..*index as usize, 1);
+            order[0] = byte;
+            byte
+        })
+        .collect()
+}
@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::comp_structs::{dict_elem::DictElem, elem_counter::ElemCounter};
+use crate::utility::{CHUNK_MAX_SIZE, MIN_OCCATIONS, VALUES};
+
+/// Bytes a chunk grows by at a time, and the smallest chunk cut early.
+const BLOCK_BYTES: usize = 1 << 15;
+
+/// Part of a block its own most common elements have to cover beyond those of the chunk for a new chunk.
+const MIN_GAIN: f64 = 0.15;
+
+/// Splits `data` into chunks of up to `max_len` bytes, at most `CHUNK_MAX_SIZE`, cutting early where the
+/// common elements change, so regions with different elements get dictionaries of their own.
+///
+/// A chunk grows a block at a time. Before a block is added, the elements most common in the block are
+/// compared to those most common in the chunk so far, by how much of the block each covers. If the block
+/// is covered much better by its own, it starts a new chunk. Data without such shifts is cut into chunks of
+/// the full size, and an empty file still gets one empty chunk.
+pub fn chunk_bounds(data: &[u8], elem_bytes: usize, max_len: usize) -> Vec<Range<usize>> {
+    let mut bounds: Vec<Range<usize>> = vec![];
+    let mut start = 0;
+    let max_len = max_len.clamp(1, CHUNK_MAX_SIZE as usize);
+
+    if data.is_empty() {
+        bounds.push(0..0);
+        return bounds;
+    }
+
+    while start < data.len() {
+        let limit = (start + max_len).min(data.len());
+        let mut end = (start + BLOCK_BYTES).min(limit);
+
+        let mut chunk_counter = ElemCounter::new(elem_bytes);
+        count_elements(&mut chunk_counter, &data[start..end], elem_bytes);
+
+        // only whole blocks can start a new chunk, a shorter one at the end stays with the chunk
+        while end + BLOCK_BYTES <= limit {
+            let block = &data[end..end + BLOCK_BYTES];
+
+            let mut block_counter = ElemCounter::new(elem_bytes);
+            count_elements(&mut block_counter, block, elem_bytes);
+
+            if shifted(&chunk_counter, &block_counter, block.len(), elem_bytes) {
+                break;
+            }
+
+            count_elements(&mut chunk_counter, block, elem_bytes);
+            end += BLOCK_BYTES;
+        }
+
+        if end + BLOCK_BYTES > limit {
+            end = limit;
+        }
+
+        bounds.push(start..end);
+        start = end;
+    }
+
+    bounds
+}
+
+/// Counts the elements at every position, as the dictionaries of all alignments together would see them.
+fn count_elements(counter: &mut ElemCounter, data: &[u8], elem_bytes: usize) {
+    for elem in data.windows(elem_bytes) {
+        counter.increment(DictElem::key_of(elem));
+    }
+}
+
+/// The elements that would fill the dictionaries of every alignment, with their counts.
+fn most_common(counter: &ElemCounter, elem_bytes: usize) -> Vec<(usize, u32)> {
+    let mut candidates = counter.at_least(MIN_OCCATIONS as u32);
+    let keep = VALUES * elem_bytes;
+
+    // only which elements make it matters, not their order
+    if candidates.len() > keep {
+        candidates.select_nth_unstable_by(keep, |a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        candidates.truncate(keep);
+    }
+
+    candidates
+}
+
+fn shifted(chunk_counter: &ElemCounter, block_counter: &ElemCounter, block_len: usize, elem_bytes: usize) -> bool {
+    let chunk_common: HashSet<usize> = most_common(chunk_counter, elem_bytes).into_iter().map(|(key, _)| key).collect();
+    let positions = (block_len + 1 - elem_bytes) as f64;
+
+    let mut own_cover = 0u64;
+    let mut chunk_cover = 0u64;
+
+    for (key, count) in most_common(block_counter, elem_bytes) {
+        own_cover += count as u64;
+
+        if chunk_common.contains(&key) {
+            chunk_cover += count as u64;
+        }
+    }
+
+    (own_cover - chunk_cover) as f64 / positions > MIN_GAIN
+}
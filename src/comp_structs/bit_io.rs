@@ -0,0 +1,64 @@
+use std::io::{Error, ErrorKind, Result};
+
+/// Packs values of up to 32 bits into bytes, most significant bit first.
+#[derive(Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        BitWriter::default()
+    }
+
+    pub fn write(&mut self, value: u32, bits: u32) {
+        self.acc = (self.acc << bits) | (value as u64 & ((1u64 << bits) - 1));
+        self.bits += bits;
+
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.acc >> self.bits) as u8);
+        }
+    }
+
+    /// Pads the last byte with zeros and returns the packed bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push((self.acc << (8 - self.bits)) as u8);
+        }
+
+        self.bytes
+    }
+}
+
+/// Reads values written by `BitWriter`.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u64,
+    bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0, acc: 0, bits: 0 }
+    }
+
+    pub fn read(&mut self, bits: u32) -> Result<u32> {
+        while self.bits < bits {
+            let byte = match self.data.get(self.pos) {
+                Some(byte) => *byte,
+                None => return Err(Error::new(ErrorKind::UnexpectedEof, "bit stream ended unexpectedly")),
+            };
+
+            self.acc = (self.acc << 8) | byte as u64;
+            self.bits += 8;
+            self.pos += 1;
+        }
+
+        self.bits -= bits;
+        Ok(((self.acc >> self.bits) & ((1u64 << bits) - 1)) as u32)
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::{Error, ErrorKind, Result};
+
+/// Set in the method byte when the dictionaries of the chunk are stored relative to the previous chunk.
+pub const DELTA_DICTS: u8 = 1 << 7;
+/// Set in the method byte when the tokens of the chunk include matches.
+pub const MATCHES: u8 = 1 << 6;
+/// Set in the method byte when the tokens of the chunk include runs.
+pub const RUNS: u8 = 1 << 5;
+/// Set in the method byte when the missed bytes of the chunk follow its tokens as a coded stream.
+pub const LITERALS: u8 = 1 << 4;
+
+/// How the payload of a chunk is encoded, stored in the byte following the chunk length.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChunkMethod {
+    /// Alignment dictionaries followed by hit and miss tokens.
+    Pairs = 0,
+    /// A Re-Pair grammar followed by the reduced sequence.
+    RePair = 1,
+    /// Alignment dictionaries followed by token streams coded with rANS.
+    PairsRans = 2,
+    /// Any number of dictionaries, selected by tokens among the hits and misses.
+    PairsSelect = 3,
+    /// The bytes of the chunk as they are.
+    Stored = 4,
+    /// Runs and literal bytes, without dictionaries.
+    Rle = 5,
+    /// The bytes of the chunk coded with rANS, without dictionaries.
+    EntropyBytes = 6,
+    /// The primary index of the Burrows-Wheeler transform, followed by a chunk of the transformed bytes.
+    Bwt = 7,
+}
+
+impl ChunkMethod {
+    pub fn from_u8(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(ChunkMethod::Pairs),
+            1 => Ok(ChunkMethod::RePair),
+            2 => Ok(ChunkMethod::PairsRans),
+            3 => Ok(ChunkMethod::PairsSelect),
+            4 => Ok(ChunkMethod::Stored),
+            5 => Ok(ChunkMethod::Rle),
+            6 => Ok(ChunkMethod::EntropyBytes),
+            7 => Ok(ChunkMethod::Bwt),
+            _ => Err(Error::new(ErrorKind::InvalidData, "unknown chunk method")),
+        }
+    }
+}
@@ -1,37 +1,63 @@
 use crate::utility;
+use crate::utility::MAX_ELEM_BYTES;
+use std::fmt;
 use std::hash::{Hash, Hasher};
 
 #[derive(Eq, Clone, Copy)]
 pub struct DictElem {
-    pub data: [u8; utility::ELEM_BYTES],
+    pub data: [u8; MAX_ELEM_BYTES],
+    pub width: u8,
     pub occurance: u64,
     pub useage: u64,
 }
 
 impl Hash for DictElem {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.data[0].hash(state);
-        self.data[1].hash(state);
+        self.bytes().hash(state);
     }
 }
 
 impl PartialEq for DictElem {
     fn eq(&self, other: &Self) -> bool {
-        self.data[0] == other.data[0] && self.data[1] == other.data[1]
+        self.bytes() == other.bytes()
     }
 }
 
 impl DictElem {
-    pub fn new(arr: [u8; utility::ELEM_BYTES], occ: u64) -> Self {
+    pub fn new(slice: &[u8], occ: u64) -> Self {
+        let mut data = [0u8; MAX_ELEM_BYTES];
+        data[..slice.len()].copy_from_slice(slice);
+
         DictElem {
-            data: arr,
+            data,
+            width: slice.len() as u8,
             occurance: occ,
             useage: 0,
         }
     }
 
-    pub fn eq_array(&self, o: &[u8; utility::ELEM_BYTES]) -> bool {
-        self.data[0] == o[0] && self.data[1] == o[1]
+    /// Creates an element from the key it is looked up by.
+    pub fn from_key(key: usize, width: usize, occ: u64) -> Self {
+        let bytes: Vec<u8> = (0..width).rev().map(|byte| (key >> (byte * 8)) as u8).collect();
+        Self::new(&bytes, occ)
+    }
+
+    /// The bytes of the element, without the unused part of the array.
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.width as usize]
+    }
+
+    /// The element read as a big endian number, used to look it up in a dictionary.
+    pub fn key(&self) -> usize {
+        Self::key_of(self.bytes())
+    }
+
+    pub fn key_of(data: &[u8]) -> usize {
+        data.iter().fold(0usize, |key, byte| (key << 8) | *byte as usize)
+    }
+
+    pub fn eq_array(&self, o: &[u8]) -> bool {
+        self.bytes() == o
     }
 
     pub fn set_occurrence(&mut self, occ: u64) {
@@ -41,11 +67,12 @@ impl DictElem {
     pub fn increment_usage(&mut self) {
         self.useage += 1;
     }
+}
 
-    pub fn to_string(&self) -> String {
-        let t0 = utility::u8_to_string(self.data[0]);
-        let t1 = utility::u8_to_string(self.data[1]);
+impl fmt::Display for DictElem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes().iter().map(|byte| utility::u8_to_string(*byte)).collect();
 
-        format!(" [{}, {}]: {} occasions", t0, t1, self.occurance)
+        write!(f, " [{}]: {} occasions", bytes.join(", "), self.occurance)
     }
 }
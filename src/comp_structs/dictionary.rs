@@ -1,45 +1,95 @@
 use crate::comp_structs::{dict_elem::DictElem, index_value_pair::IndexValuePair};
-use crate::utility::{CHUNK_MAX_SIZE, ELEM_BYTES, MIN_OCCATIONS, VALUES};
+use crate::utility::{CHUNK_MAX_SIZE, ELEM_BYTES, NR_ELEMS, VALUES};
 use std::collections::HashMap;
+use std::fmt;
+
+// marks an element without an entry in the lookup table
+const NO_INDEX: u16 = u16::MAX;
+
+/// Element to index lookup. Pairs fit in a table holding every possible pair, wider elements are
+/// hashed.
+#[derive(Clone)]
+enum Lookup {
+    Empty,
+    Table(Vec<u16>),
+    Hashed(HashMap<usize, u16>),
+}
 
+#[derive(Clone)]
 pub struct Dictionary {
-    pub elems: HashMap<usize, DictElem>,
-    pub reverse_elems: HashMap<DictElem, usize>,
+    // dense index to element array, the index of an element is its position
+    pub elems: Vec<DictElem>,
+    // element to index lookup, allocated on first insert
+    lookup: Lookup,
     pub least: IndexValuePair,
     pub coverage: u64,
+    pub elem_bytes: usize,
+}
+
+impl Default for Dictionary {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Dictionary {
     pub fn new() -> Self {
+        Self::with_elem_bytes(ELEM_BYTES)
+    }
+
+    pub fn with_elem_bytes(elem_bytes: usize) -> Self {
         Dictionary {
-            elems: HashMap::with_capacity(VALUES),
-            reverse_elems: HashMap::with_capacity(VALUES),
+            elems: Vec::with_capacity(VALUES),
+            lookup: Lookup::Empty,
             least: IndexValuePair::default(),
             coverage: CHUNK_MAX_SIZE,
+            elem_bytes,
+        }
+    }
+
+    fn set_index(&mut self, key: usize, index: u16) {
+        if let Lookup::Empty = self.lookup {
+            self.lookup = if self.elem_bytes == 2 {
+                Lookup::Table(vec![NO_INDEX; NR_ELEMS])
+            } else {
+                Lookup::Hashed(HashMap::new())
+            };
+        }
+
+        match &mut self.lookup {
+            Lookup::Table(table) => table[key] = index,
+            Lookup::Hashed(map) if index == NO_INDEX => {
+                map.remove(&key);
+            }
+            Lookup::Hashed(map) => {
+                map.insert(key, index);
+            }
+            Lookup::Empty => {}
         }
     }
 
     fn insert(&mut self, elem: &DictElem) {
-        let index = self.elems.len();
-        self.elems.insert(index, *elem);
-        self.reverse_elems.insert(*elem, index);
+        self.set_index(elem.key(), self.elems.len() as u16);
+        self.elems.push(*elem);
     }
 
     fn replace_least(&mut self, elem: &DictElem) {
-        self.reverse_elems.remove(self.elems.get(&self.least.index).unwrap());
-        self.elems.insert(self.least.index, *elem);
-        self.reverse_elems.insert(*elem, self.least.index);
+        let old = self.elems[self.least.index].key();
+        self.set_index(old, NO_INDEX);
+        self.set_index(elem.key(), self.least.index as u16);
+        self.elems[self.least.index] = *elem;
     }
 
+    // ties go to the lowest index so the same input always evicts the same element
     fn redefine_least(&mut self) {
         let mut least = IndexValuePair {
             index: 0usize,
             value: u64::MAX,
         };
 
-        for (index, elem) in self.elems.iter() {
+        for (index, elem) in self.elems.iter().enumerate() {
             if least.value > elem.occurance {
-                least.index = *index;
+                least.index = index;
                 least.value = elem.occurance;
             }
         }
@@ -47,22 +97,37 @@ impl Dictionary {
         self.least = least;
     }
 
-    fn full(&self) -> bool {
+    pub fn full(&self) -> bool {
         self.elems.len() >= VALUES
     }
 
-    pub fn consider(&mut self, elem: &DictElem) {
-        match self.reverse_elems.get(elem) {
+    fn find(&self, key: usize) -> Option<usize> {
+        let index = match &self.lookup {
+            Lookup::Empty => None,
+            Lookup::Table(table) => table.get(key).copied(),
+            Lookup::Hashed(map) => map.get(&key).copied(),
+        };
+
+        match index {
+            Some(index) if index != NO_INDEX => Some(index as usize),
+            _ => None,
+        }
+    }
+
+    /// Keeps the count of an element up to date, adding it once it was seen `min_occurrences` times and beats
+    /// the least common entry of a full dictionary.
+    pub fn consider(&mut self, elem: &DictElem, min_occurrences: u64) {
+        match self.find(elem.key()) {
             Some(index) => {
-                self.elems.insert(*index, *elem);
-                if self.least.index == *index {
+                self.elems[index] = *elem;
+                if self.least.index == index {
                     self.redefine_least();
                 }
             }
 
             None => {
-                if elem.occurance < MIN_OCCATIONS {
-                    return;
+                if elem.occurance < min_occurrences {
+                    // not seen often enough to be worth an entry
                 } else if !self.full() {
                     self.insert(elem);
                 } else if elem.occurance > self.least.value {
@@ -73,76 +138,86 @@ impl Dictionary {
         }
     }
 
-    pub fn get(&self, index: u8) -> [u8; ELEM_BYTES] {
-        self.elems.get(&(index as usize)).unwrap().data
+    /// Adds an element at the next free index, without checking if the dictionary is full.
+    pub fn push(&mut self, elem: DictElem) {
+        self.insert(&elem);
     }
 
-    pub fn get_index(&self, input: &[u8; ELEM_BYTES]) -> Option<u8> {
-        if let Some(v) = self.reverse_elems.get(&DictElem::new(*input, 0)) {
-            Some(*v as u8)
-        } else {
-            None
-        }
+    pub fn get(&self, index: u16) -> &[u8] {
+        self.elems[index as usize].bytes()
     }
 
-    pub fn purge_unused(&mut self) {
-        let mut indexes_to_remove: Vec<usize> = vec![];
+    pub fn get_elem(&self, index: u16) -> &DictElem {
+        &self.elems[index as usize]
+    }
+
+    pub fn get_index(&self, input: &[u8]) -> Option<u16> {
+        self.find(DictElem::key_of(input)).map(|index| index as u16)
+    }
 
-        for (index, elem) in self.elems.iter() {
-            if elem.useage == 0 {
-                self.reverse_elems.remove(elem);
-                indexes_to_remove.push(*index);
+    /// Removes the elements which have not been used, and returns the new index for each old index.
+    pub fn purge_unused(&mut self) -> Vec<u16> {
+        let mut remap = vec![0u16; self.elems.len()];
+        let mut unused = vec![];
+        let mut next = 0u16;
+
+        for (index, elem) in self.elems.iter().enumerate() {
+            if elem.useage > 0 {
+                remap[index] = next;
+                next += 1;
+            } else {
+                unused.push(elem.key());
             }
         }
 
-        for index in indexes_to_remove.iter() {
-            self.elems.remove(index);
+        for key in unused {
+            self.set_index(key, NO_INDEX);
         }
 
-        let elems: Vec<DictElem> = self.elems.values().map(|v| *v).collect();
-        self.elems.clear();
-        self.reverse_elems.clear();
+        // keep the remaining elements in their current order and give them new indexes
+        self.elems.retain(|elem| elem.useage > 0);
 
-        for (index, elem) in elems.iter().enumerate() {
-            self.elems.insert(index, *elem);
-            self.reverse_elems.insert(*elem, index);
+        for index in 0..self.elems.len() {
+            self.set_index(self.elems[index].key(), index as u16);
         }
 
         self.elems.shrink_to_fit();
-        self.reverse_elems.shrink_to_fit();
+        remap
     }
 
-    pub fn len(&self) -> u8 {
-        self.elems.len() as u8
+    pub fn len(&self) -> usize {
+        self.elems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elems.is_empty()
     }
 
     pub fn to_vec(&self) -> Vec<u8> {
-        let mut out: Vec<u8> = Vec::with_capacity(self.elems.len() * ELEM_BYTES);
+        let mut out: Vec<u8> = Vec::with_capacity(self.elems.len() * self.elem_bytes);
 
-        for index in 0usize..self.elems.len() {
-            out.extend(self.elems.get(&index).unwrap().data);
+        for elem in self.elems.iter() {
+            out.extend(elem.bytes());
         }
 
         out
     }
 
-    pub fn increment_usage(&mut self, index: u8) {
-        if let Some(elem) = self.elems.get_mut(&(index as usize)) {
+    pub fn increment_usage(&mut self, index: u16) {
+        if let Some(elem) = self.elems.get_mut(index as usize) {
             elem.increment_usage();
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
-        let mut out_str = String::from(format!(
-            "coverage: {} bytes. Elements: {}",
-            self.coverage,
-            self.elems.len()
-        ));
+impl fmt::Display for Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "coverage: {} bytes. Elements: {}", self.coverage, self.elems.len())?;
 
-        for (index, element) in self.elems.iter() {
-            out_str.push_str(format!("\nElem {}: {}", index, element.to_string()).as_str());
+        for (index, element) in self.elems.iter().enumerate() {
+            write!(f, "\nElem {}: {}", index, element)?;
         }
 
-        out_str
+        Ok(())
     }
 }
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use crate::utility::NR_ELEMS;
+
+/// Counts how often each element occurs. Pairs are counted in a table holding every possible pair,
+/// wider elements in a map.
+pub enum ElemCounter {
+    Table(Vec<u32>),
+    Map(HashMap<usize, u32>),
+}
+
+impl ElemCounter {
+    pub fn new(elem_bytes: usize) -> Self {
+        if elem_bytes == 2 {
+            ElemCounter::Table(vec![0u32; NR_ELEMS])
+        } else {
+            ElemCounter::Map(HashMap::new())
+        }
+    }
+
+    /// Counts one more occurrence of the element and returns its new count.
+    pub fn increment(&mut self, key: usize) -> u32 {
+        let count = match self {
+            ElemCounter::Table(table) => &mut table[key],
+            ElemCounter::Map(map) => map.entry(key).or_insert(0),
+        };
+
+        *count += 1;
+        *count
+    }
+
+    /// All elements seen at least `min` times, as keys with their counts.
+    pub fn at_least(&self, min: u32) -> Vec<(usize, u32)> {
+        match self {
+            ElemCounter::Table(table) => (0..NR_ELEMS)
+                .filter(|key| table[*key] >= min)
+                .map(|key| (key, table[key]))
+                .collect(),
+            ElemCounter::Map(map) => map
+                .iter()
+                .filter(|(_, count)| **count >= min)
+                .map(|(key, count)| (*key, *count))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::io::{Error, ErrorKind, Result};
+
+use crate::filter::Filter;
+use crate::utility::{ELEM_BYTES, MAX_ELEM_BYTES, MIN_ELEM_BYTES};
+
+/// Marks a file written with a header, files without it start directly with the number of layers.
+pub const MAGIC: [u8; 3] = *b"PYC";
+/// Version 2 added the method byte at the start of each chunk, version 3 the flags byte, version 4 varints for
+/// the lengths of chunks and long misses.
+pub const FORMAT_VERSION: u8 = 4;
+
+/// The layers are coded by the Huffman stage.
+const FLAG_HUFFMAN: u8 = 1;
+/// The layers were compressed with a preset, whose id follows the flags in 4 bytes.
+const FLAG_PRESET: u8 = 2;
+/// Filters were applied before the first layer. Their number follows in 1 byte, and 2 bytes for each.
+const FLAG_FILTERS: u8 = 4;
+
+/// Settings a compressed file was written with, stored at the start of the file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    /// 0 for files written before the header existed.
+    pub version: u8,
+    pub layers: u8,
+    pub elem_bytes: usize,
+    pub huffman: bool,
+    /// Id of the preset dictionaries the file needs to be decompressed.
+    pub preset: Option<u32>,
+    /// Filters to revert after the last layer, in the order they were applied.
+    pub filters: Vec<Filter>,
+}
+
+impl Header {
+    pub fn new(layers: u8, elem_bytes: usize) -> Self {
+        Header { version: FORMAT_VERSION, layers, elem_bytes, huffman: false, preset: None, filters: vec![] }
+    }
+
+    /// Chunks of files before version 2 always hold pair tokens, without a method byte.
+    pub fn has_chunk_method(&self) -> bool {
+        self.version >= 2
+    }
+
+    /// Chunks of files before version 4 start with their total length in 4 bytes, and long misses hold the
+    /// number of bytes of their length.
+    pub fn has_varints(&self) -> bool {
+        self.version >= 4
+    }
+
+    /// The header as it is stored, failing for more filters than their count byte holds.
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut out = MAGIC.to_vec();
+        out.push(self.version);
+        out.push(self.elem_bytes as u8);
+        out.push(self.layers);
+
+        if self.version >= 3 {
+            let mut flags = 0;
+
+            if self.huffman {
+                flags |= FLAG_HUFFMAN;
+            }
+
+            if self.preset.is_some() {
+                flags |= FLAG_PRESET;
+            }
+
+            if !self.filters.is_empty() {
+                flags |= FLAG_FILTERS;
+            }
+
+            out.push(flags);
+        }
+
+        if let Some(id) = self.preset {
+            out.extend(id.to_be_bytes());
+        }
+
+        if !self.filters.is_empty() {
+            if self.filters.len() > u8::MAX as usize {
+                return Err(Error::new(ErrorKind::InvalidInput, "a file holds at most 255 filters"));
+            }
+
+            out.push(self.filters.len() as u8);
+
+            for filter in self.filters.iter() {
+                out.extend(filter.to_bytes());
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Reads the header from the start of a compressed file, returning it and its length in bytes.
+    pub fn read(data: &[u8]) -> Result<(Header, usize)> {
+        if data.len() < MAGIC.len() + 3 || data[..MAGIC.len()] != MAGIC {
+            // files from before the header only hold the number of layers
+            return match data.first() {
+                Some(layers) => Ok((
+                    Header {
+                        version: 0,
+                        layers: *layers,
+                        elem_bytes: ELEM_BYTES,
+                        huffman: false,
+                        preset: None,
+                        filters: vec![],
+                    },
+                    1,
+                )),
+                None => Err(Error::new(ErrorKind::UnexpectedEof, "compressed file is empty")),
+            };
+        }
+
+        let version = data[MAGIC.len()];
+        let elem_bytes = data[MAGIC.len() + 1] as usize;
+        let layers = data[MAGIC.len() + 2];
+
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "unsupported format version"));
+        }
+
+        if !(MIN_ELEM_BYTES..=MAX_ELEM_BYTES).contains(&elem_bytes) {
+            return Err(Error::new(ErrorKind::InvalidData, "unsupported element width"));
+        }
+
+        let mut header = Header::new(layers, elem_bytes);
+        header.version = version;
+        let mut len = MAGIC.len() + 3;
+
+        if version >= 3 {
+            let flags = *data.get(len).ok_or_else(header_ended)?;
+
+            if flags & !(FLAG_HUFFMAN | FLAG_PRESET | FLAG_FILTERS) != 0 {
+                return Err(Error::new(ErrorKind::InvalidData, "unsupported header flags"));
+            }
+
+            header.huffman = flags & FLAG_HUFFMAN != 0;
+            len += 1;
+
+            if flags & FLAG_PRESET != 0 {
+                let id = data.get(len..len + 4).ok_or_else(header_ended)?;
+
+                header.preset = Some(u32::from_be_bytes([id[0], id[1], id[2], id[3]]));
+                len += 4;
+            }
+
+            if flags & FLAG_FILTERS != 0 {
+                let count = *data.get(len).ok_or_else(header_ended)? as usize;
+                let bytes = data.get(len + 1..len + 1 + 2 * count).ok_or_else(header_ended)?;
+
+                for filter in bytes.chunks_exact(2) {
+                    header.filters.push(Filter::from_bytes([filter[0], filter[1]])?);
+                }
+
+                len += 1 + 2 * count;
+            }
+        }
+
+        Ok((header, len))
+    }
+}
+
+fn header_ended() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "compressed file header ended unexpectedly")
+}
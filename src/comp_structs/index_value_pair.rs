@@ -14,7 +14,7 @@ impl PartialEq for IndexValuePair {
 
 impl PartialOrd for IndexValuePair {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(&other))
+        Some(self.cmp(other))
     }
 }
 
@@ -1,3 +1,9 @@
+pub mod bit_io;
+pub mod chunk_method;
 pub mod dict_elem;
 pub mod index_value_pair;
-pub mod dictionary;
\ No newline at end of file
+pub mod dictionary;
+pub mod elem_counter;
+pub mod header;
+pub mod options;
+pub mod token;
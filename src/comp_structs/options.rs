@@ -0,0 +1,160 @@
+use std::io::{Error, ErrorKind, Result};
+
+use crate::filter::Filter;
+use crate::preset::Preset;
+use crate::utility::{CHUNK_MAX_SIZE, ELEM_BYTES, MAX_LAYERS, MAX_LEVEL, MIN_LAYER_GAIN, MIN_LEVEL, MIN_OCCATIONS};
+
+/// How each layer encodes its chunks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Dictionaries of frequent elements, replacing pairs one layer at a time.
+    Pairs,
+    /// Recursive pair replacement within each chunk, building a grammar in memory.
+    RePair,
+}
+
+/// Settings for compressing a file.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Bytes in each dictionary element, 2 to 4. Wider elements suit record oriented data.
+    pub elem_bytes: usize,
+    pub mode: Mode,
+    /// Most bytes in a chunk, up to `CHUNK_MAX_SIZE`. Smaller chunks adapt their dictionaries sooner.
+    pub chunk_size: usize,
+    /// Times an element has to be seen in a chunk to get into its dictionaries.
+    pub min_occurrences: u64,
+    /// Codes the final layer with canonical Huffman codes.
+    pub huffman: bool,
+    /// Codes the tokens of pair chunks with rANS instead of writing them byte by byte.
+    pub rans: bool,
+    /// Refines the dictionaries of each chunk by the bytes their entries save, rather than their counts.
+    pub cost_select: bool,
+    /// Parses chunks for the fewest bytes rather than greedily, in the same token format.
+    pub optimal_parse: bool,
+    /// Repeats earlier bytes of a chunk with match tokens, where they take fewer bytes than hits would.
+    pub matches: bool,
+    /// Codes long runs of a byte, and of the element of a hit, with run tokens, and tries chunks as runs and
+    /// literal bytes.
+    pub runs: bool,
+    /// Codes the missed bytes of a chunk apart from its tokens, where that takes fewer bytes.
+    pub literals: bool,
+    /// Also tries coding whole chunks as plain bytes with rANS, where the pairs do worse.
+    pub entropy_chunks: bool,
+    /// Builds the dictionaries from the counts of the whole chunk instead of as the chunk is read.
+    pub exact_dicts: bool,
+    /// Also tries dictionaries for regions of each chunk, selected by explicit tokens.
+    pub regions: bool,
+    /// Also tries each chunk of the first layer after the Burrows-Wheeler transform and move-to-front.
+    pub bwt: bool,
+    /// Most layers written, whatever they would save.
+    pub max_layers: u8,
+    /// Part of its input a layer has to save to be kept, which also skips layers predicted to save too little.
+    pub min_gain: f64,
+    /// Reversible transforms applied in order before the first layer.
+    pub filters: Vec<Filter>,
+    /// Dictionaries trained on similar files, which chunks can store their own dictionaries relative to.
+    pub preset: Option<Preset>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            elem_bytes: ELEM_BYTES,
+            mode: Mode::Pairs,
+            chunk_size: CHUNK_MAX_SIZE as usize,
+            min_occurrences: MIN_OCCATIONS,
+            huffman: false,
+            rans: false,
+            cost_select: false,
+            optimal_parse: false,
+            matches: true,
+            runs: true,
+            literals: true,
+            entropy_chunks: false,
+            exact_dicts: false,
+            regions: false,
+            bwt: false,
+            max_layers: MAX_LAYERS,
+            min_gain: MIN_LAYER_GAIN,
+            filters: vec![],
+            preset: None,
+        }
+    }
+}
+
+impl Options {
+    /// The options of a compression level, from `MIN_LEVEL`, the fastest, to `MAX_LEVEL`, the smallest.
+    /// `DEFAULT_LEVEL` gives the default options.
+    ///
+    /// | level | chunk size | min count | layers | tokens        | dicts   | parse   | entropy stage             |
+    /// |-------|------------|-----------|--------|---------------|---------|---------|---------------------------|
+    /// | 1     | 128 KiB    | 8         | 1      | runs          | counts  | greedy  | none                      |
+    /// | 2     | 256 KiB    | 6         | 2      | runs          | counts  | greedy  | none                      |
+    /// | 3     | 790 KB     | 4         | any    | runs          | counts  | greedy  | literals                  |
+    /// | 4     | 790 KB     | 4         | any    | matches, runs | counts  | greedy  | literals                  |
+    /// | 5     | 790 KB     | 4         | any    | matches, runs | counts  | greedy  | literals, chunks, Huffman |
+    /// | 6     | 790 KB     | 4         | any    | matches, runs | counts  | greedy  | rANS, chunks, Huffman     |
+    /// | 7     | 790 KB     | 4         | any    | matches, runs | savings | greedy  | rANS, chunks, Huffman     |
+    /// | 8     | 790 KB     | 4         | any    | matches, runs | savings | optimal | rANS, chunks, Huffman     |
+    /// | 9     | 790 KB     | 4         | any    | matches, runs | exact   | optimal | rANS, chunks, Huffman     |
+    ///
+    /// Dictionaries are built from the counts as the chunk is read, refined by what their entries save
+    /// (`cost_select`), or from the counts of the whole chunk and then refined (`exact_dicts`). Literals are the
+    /// missed bytes coded with rANS, and chunks are whole chunks coded with rANS, see `literals` and
+    /// `entropy_chunks`. Every level also tries storing chunks or writing them as runs. Level 9 also encodes
+    /// layers without predicting them first, until one saves nothing.
+    ///
+    /// Higher levels are not always smaller, as refining the dictionaries of a layer can leave the layers above
+    /// it fewer pairs.
+    pub fn with_level(level: u8) -> Result<Self> {
+        let default = Options::default();
+
+        let options = match level {
+            1 => Options {
+                chunk_size: 1 << 17,
+                min_occurrences: 8,
+                max_layers: 1,
+                matches: false,
+                literals: false,
+                ..default
+            },
+            2 => Options {
+                chunk_size: 1 << 18,
+                min_occurrences: 6,
+                max_layers: 2,
+                matches: false,
+                literals: false,
+                ..default
+            },
+            3 => Options { matches: false, ..default },
+            4 => default,
+            5 => Options { huffman: true, entropy_chunks: true, ..default },
+            6 => Options { huffman: true, entropy_chunks: true, rans: true, ..default },
+            7 => Options { huffman: true, entropy_chunks: true, rans: true, cost_select: true, ..default },
+            8 => Options {
+                huffman: true,
+                entropy_chunks: true,
+                rans: true,
+                cost_select: true,
+                optimal_parse: true,
+                ..default
+            },
+            9 => Options {
+                huffman: true,
+                entropy_chunks: true,
+                rans: true,
+                cost_select: true,
+                optimal_parse: true,
+                exact_dicts: true,
+                min_gain: 0.0,
+                ..default
+            },
+            _ => {
+                let message = format!("level must be between {} and {}", MIN_LEVEL, MAX_LEVEL);
+                return Err(Error::new(ErrorKind::InvalidInput, message));
+            }
+        };
+
+        Ok(options)
+    }
+}
@@ -0,0 +1,16 @@
+use std::ops::Range;
+
+/// A piece of an encoded chunk, kept in memory until the dictionaries have been purged.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    /// Element `index` of dictionary `dict`.
+    Hit { dict: u8, index: u16 },
+    /// Bytes of the chunk which are written as they are.
+    Miss(Range<usize>),
+    /// `len` bytes repeated from `distance` bytes back in the chunk.
+    Match { distance: usize, len: usize },
+    /// `byte` repeated `len` times.
+    ByteRun { byte: u8, len: usize },
+    /// The element of the previous hit repeated this many times.
+    HitRun(usize),
+}
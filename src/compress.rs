@@ -1,296 +1,980 @@
 use std::fs::{File, OpenOptions};
-use std::io::prelude::*;
-use std::io::{BufReader, BufWriter, Result, SeekFrom, Write};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-
-use crate::comp_structs::{dict_elem::DictElem, dictionary::Dictionary};
+use std::rc::Rc;
+
+use crate::bwt;
+use crate::chunker;
+use crate::comp_structs::{
+    chunk_method::{ChunkMethod, LITERALS, MATCHES, RUNS},
+    dict_elem::DictElem,
+    dictionary::Dictionary,
+    elem_counter::ElemCounter,
+    header::Header,
+    options::{Mode, Options},
+    token::Token,
+};
+use crate::dict_delta;
+use crate::filter::{self, Filter};
+use crate::huffman;
+use crate::input::Input;
+use crate::matcher::Matcher;
+use crate::optimal_parse;
+use crate::prediction;
+use crate::rans;
+use crate::regions;
+use crate::repair;
+use crate::selection;
+use crate::strategy;
 use crate::utility;
 use crate::utility::{
-    Reader, Writer, CHUNK_MAX_SIZE, DEBUG, DETAILED_DEBUG, DEBUG_DICT, ELEM_BYTES, ELEM_HALF, NR_ELEMS, VALUES_HALF,
+    BYTE_RUN_MARK, DEBUG, DETAILED_DEBUG, DEBUG_DICT, EXT_DICT_LEN, EXT_HIT_MARK, HIT_RUN_MARK,
+    LONG_MISS_BITS, MATCH_MARK, MAX_MATCH, MAX_VALUES, MIN_BYTE_RUN, MIN_HIT_RUN, MIN_MATCH, VALUES, VALUES_HALF,
 };
 
-pub fn run(path: &PathBuf) -> Result<PathBuf> {
-    println!("\nCompressing file: {}", path.file_name().unwrap().to_str().unwrap());
+pub fn run(path: &Path, options: &Options) -> Result<PathBuf> {
+    if options.preset.as_ref().is_some_and(|preset| preset.elem_bytes != options.elem_bytes) {
+        return Err(Error::new(ErrorKind::InvalidInput, "preset was trained for another element width"));
+    }
 
-    let mut old_path = path.to_owned();
-    let mut new_path = path.to_owned();
+    // the preset dictionaries for the layer with the given index, counted from the bottom
+    let preset_layer = |layer: u8| options.preset.as_ref().map_or(&[][..], |preset| preset.layer(layer as usize));
+    // the transform is only tried on the chunks of the input, those of the layers above hold tokens
+    let at_layer = |options: &Options, layer: u8| Options { bwt: options.bwt && layer == 0, ..options.clone() };
+
+    // layers are written greedily and byte by byte, which leaves the next layer the most pairs to find, the
+    // slower options only pay off at the top
+    let layer_options =
+        Options { rans: false, optimal_parse: false, literals: false, entropy_chunks: false, ..options.clone() };
+    let recode = (options.rans || options.optimal_parse || options.literals || options.entropy_chunks)
+        && options.mode == Mode::Pairs;
+
+    // the header has to hold the filters, which is checked before any file is written
+    Header { filters: options.filters.clone(), ..Header::new(0, options.elem_bytes) }.to_vec()?;
+
+    // the filtered file takes the place of the original as the input of the first layer
+    let source = if options.filters.is_empty() { path.to_owned() } else { filter_file(path, &options.filters)? };
+
+    // the output of the last layer that made the file smaller, the input of that layer and of the one below it.
+    // Each is read once, as a pipe can not be read again
+    let mut top = LayerInput::open(&source)?;
+    let mut top_input = top.clone();
+    let mut below_input = top.clone();
     let mut layers = 0;
-    let mut continue_compress = true;
+    // if the loop stopped at a layer that was encoded but did not save enough
+    let mut dropped = false;
+
+    while layers < options.max_layers {
+        let size = top.data.len() as u64;
+        let required = (size as f64 * options.min_gain) as u64;
+
+        // the prediction is rough, so only layers predicted to save less than half of what is required are
+        // skipped before encoding them. It knows nothing of the preset dictionaries, so layers with them are
+        // always encoded, as are all layers when any saving is enough
+        if options.mode == Mode::Pairs && options.min_gain > 0.0 && preset_layer(layers).is_empty() {
+            // the layers are written with matches even when the top is recoded with rANS
+            let predicted = prediction::layer_gain(&top.data, options.elem_bytes, layer_options.matches);
+
+            if DEBUG {
+                println!("\nLayer {} predicted to save {} of {} Bytes", layers + 1, predicted, size);
+            }
+
+            if predicted < (required / 2) as i64 {
+                break;
+            }
+        }
+
+        let current = at_layer(&layer_options, layers);
+        let new_path = compress_file_layer(&top.path, &top.data, &current, preset_layer(layers))?;
+        let new_size = new_path.metadata()?.len();
+
+        if new_size >= size || size - new_size < required {
+            std::fs::remove_file(&new_path)?;
+            dropped = true;
+            break;
+        }
+
+        let new_top = LayerInput::open(&new_path)?;
+
+        // remove temporary files that are finished
+        std::mem::replace(&mut below_input, std::mem::replace(&mut top_input, std::mem::replace(&mut top, new_top)))
+            .remove_unless(&source)?;
+        layers += 1;
+    }
+
+    let mut layer = top.data.to_vec();
+
+    // only the top two layers are recoded with the full options, as a top layer which saves little with the
+    // greedy options often does worse recoded than the layer below it, and the dropped layer above them, as the
+    // full options can still save enough where the greedy layer did not
+    if recode {
+        let mut inputs = vec![];
 
-    while continue_compress {
-        // if the layer is above 1 then remove temporary file
         if layers > 1 {
-            std::fs::remove_file(&old_path)?;
+            inputs.push((&below_input, layers - 2));
         }
 
-        old_path = new_path;
-        let mut dict_collection = generate_dict_collection(&old_path)?;
-        new_path = compress_layer(&old_path, &mut dict_collection)?;
+        if layers > 0 {
+            inputs.push((&top_input, layers - 1));
+        }
+
+        if dropped {
+            inputs.push((&top, layers));
+        }
+
+        for (input, below) in inputs {
+            let input_size = input.data.len() as u64;
+            let required = (input_size as f64 * options.min_gain) as u64;
+
+            let buf = recode_layer(&input.data, &at_layer(options, below), preset_layer(below))?;
+            let saves_enough = (buf.len() as u64) < input_size && input_size - buf.len() as u64 >= required;
 
-        continue_compress = utility::file_is_larger(&old_path, &new_path);
-        //continue_compress = false;
-        layers += if continue_compress { 1 } else { 0 };
+            if saves_enough && buf.len() < layer.len() {
+                if DEBUG {
+                    println!("\nUsing the recoded layer {}: {} Bytes", below + 1, buf.len());
+                }
+
+                layers = below + 1;
+                layer = buf;
+            }
+        }
     }
 
-    let final_path = finalize_file(&old_path, layers)?;
+    below_input.remove_unless(&source)?;
+    top_input.remove_unless(&source)?;
+    let top_path = top.path.clone();
+    drop(top);
 
-    // only remove old file if there is more than one layer
-    if layers > 1 {
-        std::fs::remove_file(&old_path)?;
+    let mut header = Header::new(layers, options.elem_bytes);
+    header.huffman = options.huffman;
+    header.filters = options.filters.clone();
+
+    // the grammar chunks have no dictionaries to store relative to the preset, and a file without layers
+    // does not need it either
+    if options.mode == Mode::Pairs && layers > 0 {
+        header.preset = options.preset.as_ref().map(|preset| preset.id());
+    }
+
+    let final_path = finalize_file(&top_path, &layer, &header)?;
+
+    if top_path != path {
+        std::fs::remove_file(&top_path)?;
     }
 
-    std::fs::remove_file(&new_path)?;
+    if source != path && source != top_path {
+        std::fs::remove_file(&source)?;
+    }
 
     Ok(final_path)
 }
 
-fn generate_dict_collection(path: &Path) -> Result<Vec<(Dictionary, Dictionary)>> {
-    let mut dict_collection: Vec<(Dictionary, Dictionary)> = vec![];
-    let file_length = path.metadata()?.len();
-    let chunks = 1 + (file_length / CHUNK_MAX_SIZE);
+/// Writes the filtered file next to the original.
+fn filter_file(path: &Path, filters: &[Filter]) -> Result<PathBuf> {
+    let input = Input::open(path)?;
+    let (filtered_path, mut writer) = get_path_and_writer(path)?;
+
+    writer.write_all(&filter::apply_all(filters, &input))?;
+    writer.flush()?;
+
+    Ok(filtered_path)
+}
+
+/// The input of a layer and the file it was read from, shared while the layers above it can still be recoded.
+#[derive(Clone)]
+struct LayerInput {
+    path: PathBuf,
+    data: Rc<Input>,
+}
+
+impl LayerInput {
+    fn open(path: &Path) -> Result<Self> {
+        Ok(LayerInput { path: path.to_owned(), data: Rc::new(Input::open(path)?) })
+    }
 
-    for chunk in 0..chunks {
-        let offset = chunk * CHUNK_MAX_SIZE;
-        let mut pair = generate_dict_pair(path, offset)?;
+    /// Removes the file, unless it is the `source` of the layers.
+    fn remove_unless(self, source: &Path) -> Result<()> {
+        // the file stays mapped until the data is dropped
+        drop(self.data);
 
-        let chunk_size = file_length - offset;
-        if chunk_size < CHUNK_MAX_SIZE {
-            pair.0.coverage = chunk_size;
-            pair.1.coverage = chunk_size;
+        if self.path != source {
+            std::fs::remove_file(&self.path)?;
         }
 
-        dict_collection.push(pair);
+        Ok(())
+    }
+}
+
+/// Encodes the `data` read from `path` as a layer, into a file next to it.
+fn compress_file_layer(path: &Path, data: &Input, options: &Options, preset: &[Dictionary]) -> Result<PathBuf> {
+    if DEBUG {
+        println!("\nLayer input mapped: {}", data.is_mapped());
     }
 
-    Ok(dict_collection)
+    match options.mode {
+        Mode::Pairs => {
+            let mut dict_collection = generate_dict_collection(data, options);
+            compress_layer(path, data, &mut dict_collection, options, preset)
+        }
+        Mode::RePair => repair::compress_layer(path, data, options.chunk_size),
+    }
 }
 
-fn generate_dict_pair(path: &Path, offset: u64) -> Result<(Dictionary, Dictionary)> {
-    let even_dict = generate_dict(path, offset)?;
-    let odd_dict = generate_dict(path, offset + 1)?;
+/// Encodes a layer in memory with the full options, using the slower parse and token coding.
+fn recode_layer(data: &[u8], options: &Options, preset: &[Dictionary]) -> Result<Vec<u8>> {
+    let mut dict_collection = generate_dict_collection(data, options);
 
-    Ok((even_dict, odd_dict))
+    let mut buf: Vec<u8> = vec![];
+    encode_layer(data, &mut dict_collection, &mut buf, options, preset)?;
+
+    Ok(buf)
 }
 
-fn generate_dict(path: &Path, offset: u64) -> Result<Dictionary> {
-    let mut dict = Dictionary::new();
-    let mut reader = BufReader::new(File::open(path)?);
-    let mut buf = [0u8; ELEM_BYTES];
-    let mut counter = [0u32; NR_ELEMS];
+/// Splits the data into chunks of up to the chunk size of the options, and generates the dictionaries of each.
+pub fn generate_dict_collection(data: &[u8], options: &Options) -> Vec<Vec<Dictionary>> {
+    let elem_bytes = options.elem_bytes;
 
-    reader.seek(SeekFrom::Start(offset))?;
-    let nr_reads = CHUNK_MAX_SIZE / ELEM_BYTES as u64;
+    chunker::chunk_bounds(data, elem_bytes, options.chunk_size)
+        .into_iter()
+        .map(|bounds| generate_dicts(&data[bounds], options))
+        .collect()
+}
 
-    for _ in 0..nr_reads {
-        match reader.read_exact(&mut buf) {
-            Ok(()) => {
-                let index = ((buf[0] as usize) << 8) | (buf[1] as usize);
-                counter[index] += 1;
-                let dict_elem = DictElem::new(buf, counter[index] as u64);
-                dict.consider(&dict_elem);
-            }
+/// Generates the dictionaries of a chunk the options ask for, covering the whole chunk.
+fn generate_dicts(chunk: &[u8], options: &Options) -> Vec<Dictionary> {
+    let elem_bytes = options.elem_bytes;
 
-            Err(_e) => {
-                // reached end of file
-                break;
-            }
-        }
+    let mut dicts = if options.exact_dicts {
+        (0..elem_bytes as u64)
+            .map(|alignment| generate_exact_dict(chunk, alignment, elem_bytes, options.min_occurrences))
+            .collect()
+    } else {
+        generate_chunk_dicts(chunk, elem_bytes, options.min_occurrences)
+    };
+
+    for dict in dicts.iter_mut() {
+        dict.coverage = chunk.len() as u64;
     }
 
-    Ok(dict)
+    dicts
 }
 
-fn compress_layer(path: &Path, dicts: &mut [(Dictionary, Dictionary)]) -> Result<PathBuf> {
-    // creater reader, writer, and buffers
-    let mut reader = BufReader::new(File::open(path)?);
-    let (path_comp, mut writer) = get_path_and_writer(path)?;
+/// Generates one dictionary for each alignment of the elements in the chunk.
+fn generate_chunk_dicts(chunk: &[u8], elem_bytes: usize, min_occurrences: u64) -> Vec<Dictionary> {
+    (0..elem_bytes as u64)
+        .map(|alignment| generate_dict(chunk, alignment, elem_bytes, min_occurrences))
+        .collect()
+}
 
-    let mut hits: u64 = 0;
-    let mut misses: u64 = 0;
-    let mut dict_bytes: u64 = 0;
-    let mut overhead: u64 = (dicts.len() * 4) as u64;
+/// Generates a dictionary of the elements seen at least `min_occurrences` times from `offset` on.
+pub fn generate_dict(data: &[u8], offset: u64, elem_bytes: usize, min_occurrences: u64) -> Dictionary {
+    let mut dict = Dictionary::with_elem_bytes(elem_bytes);
+    let mut counter = ElemCounter::new(elem_bytes);
+
+    // the data is the chunk, of whatever size the options give it
+    let start = (offset as usize).min(data.len());
+
+    for elem in data[start..].chunks_exact(elem_bytes) {
+        let count = counter.increment(DictElem::key_of(elem));
+        let dict_elem = DictElem::new(elem, count as u64);
+        dict.consider(&dict_elem, min_occurrences);
+    }
+
+    dict
+}
+
+/// Generates a dictionary of up to `MAX_VALUES` elements, holding the most common elements first so
+/// they get the one byte hits.
+pub fn generate_ext_dict(data: &[u8], offset: u64, elem_bytes: usize, min_occurrences: u64) -> Dictionary {
+    generate_ranked_dict(data, offset, elem_bytes, min_occurrences, MAX_VALUES)
+}
+
+/// Generates a dictionary of the elements seen most often from `offset` on, counting all of them before
+/// choosing, where `generate_dict` keeps the most common elements seen so far as it goes.
+pub fn generate_exact_dict(data: &[u8], offset: u64, elem_bytes: usize, min_occurrences: u64) -> Dictionary {
+    generate_ranked_dict(data, offset, elem_bytes, min_occurrences, VALUES)
+}
 
-    for dict_index in 0..dicts.len() {
-        // init dictionary references
-        let mut dict_refs = [&mut dicts[dict_index].0, &mut dicts[dict_index].1];
+fn generate_ranked_dict(data: &[u8], offset: u64, elem_bytes: usize, min_occurrences: u64, len: usize) -> Dictionary {
+    let mut dict = Dictionary::with_elem_bytes(elem_bytes);
+    let mut counter = ElemCounter::new(elem_bytes);
 
-        // dry run
-        compress_chunk(true, &mut dict_refs, &mut reader, &mut writer)?;
+    let start = (offset as usize).min(data.len());
 
-        //remove unused elements from dictionaries to save extra space
-        dict_refs[0].purge_unused();
-        dict_refs[1].purge_unused();
+    for elem in data[start..].chunks_exact(elem_bytes) {
+        counter.increment(DictElem::key_of(elem));
+    }
+
+    let mut candidates = counter.at_least(min_occurrences as u32);
 
-        // 1 bytes overhead for each dictionary, and each element uses 2 bytes
-        dict_bytes += 2 + 2 * dict_refs[0].len() as u64 + 2 * dict_refs[1].len() as u64;
+    // ties are ordered by the element itself to keep the output reproducible
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    candidates.truncate(len);
 
-        // real run
-        let (h, m, o) = compress_chunk(false, &mut dict_refs, &mut reader, &mut writer)?;
-        hits += h;
-        misses += m;
-        overhead += o;
+    for (key, count) in candidates {
+        dict.push(DictElem::from_key(key, elem_bytes, count as u64));
     }
 
+    dict
+}
+
+pub fn compress_layer(
+    path: &Path,
+    data: &[u8],
+    dicts: &mut [Vec<Dictionary>],
+    options: &Options,
+    preset: &[Dictionary],
+) -> Result<PathBuf> {
+    // create writer and buffers
+    let (path_comp, mut writer) = get_path_and_writer(path)?;
+    let (hits, misses, dict_bytes, overhead) = encode_layer(data, dicts, &mut writer, options, preset)?;
+
     // make sure all buffers are written to file
     writer.flush()?;
 
     if DEBUG {
-        print_comp_result(dicts, path, &path_comp, hits, misses, dict_bytes, overhead)?;
+        print_comp_result(dicts, data.len() as u64, &path_comp, hits, misses, dict_bytes, overhead)?;
     }
 
     Ok(path_comp)
 }
 
-fn compress_chunk(
-    dry: bool,
-    dicts: &mut [&mut Dictionary; 2],
-    reader: &mut Reader,
-    writer: &mut Writer,
+/// Encodes every chunk of a layer, returning the bytes covered by hits and misses, and the bytes spent on
+/// dictionaries and token overhead.
+///
+/// The first chunk stores its dictionaries relative to the `preset` dictionaries of the layer, if there are
+/// any, and each chunk can use the preset dictionaries instead of its own. With `bwt` each chunk is also
+/// encoded after the Burrows-Wheeler transform, and keeps whichever is smaller.
+pub fn encode_layer<W: Write>(
+    data: &[u8],
+    dicts: &mut [Vec<Dictionary>],
+    writer: &mut W,
+    options: &Options,
+    preset: &[Dictionary],
+) -> Result<(u64, u64, u64, u64)> {
+    let mut hits: u64 = 0;
+    let mut misses: u64 = 0;
+    let mut dict_bytes: u64 = 0;
+    let mut overhead: u64 = (dicts.len() * 5) as u64;
+
+    let mut offset = 0usize;
+    let mut previous: Vec<Dictionary> = preset.to_vec();
+
+    for chunk_dicts in dicts.iter_mut() {
+        // get the slice of data covered by the chunk
+        let chunk_len = chunk_dicts[0].coverage as usize;
+        let chunk = &data[offset..offset + chunk_len];
+        offset += chunk_len;
+
+        let before = if options.bwt { Some(previous.clone()) } else { None };
+        let mut buf_chunk: Vec<u8> = vec![];
+        let mut counts = encode_layer_chunk(chunk, chunk_dicts, &mut previous, &mut buf_chunk, options, preset)?;
+
+        // the transformed bytes are encoded as a chunk of their own, following the primary index
+        if let Some(mut bwt_previous) = before {
+            let (transformed, primary) = bwt::encode(chunk);
+            let mut bwt_dicts = generate_dicts(&transformed, options);
+
+            let mut payload: Vec<u8> = vec![];
+            utility::write_varint(&mut payload, primary as u64);
+            let bwt_counts =
+                encode_layer_chunk(&transformed, &mut bwt_dicts, &mut bwt_previous, &mut payload, options, preset)?;
+
+            let mut buf_bwt: Vec<u8> = vec![];
+            write_chunk(&mut buf_bwt, ChunkMethod::Bwt as u8, &payload)?;
+
+            if buf_bwt.len() < buf_chunk.len() {
+                if DEBUG {
+                    println!("Using the transformed chunk: {} -> {} Bytes", buf_chunk.len(), buf_bwt.len());
+                }
+
+                buf_chunk = buf_bwt;
+                counts = bwt_counts;
+                previous = bwt_previous;
+                *chunk_dicts = bwt_dicts;
+            }
+        }
+
+        writer.write_all(&buf_chunk)?;
+
+        hits += counts.0;
+        misses += counts.1;
+        dict_bytes += counts.2;
+        overhead += counts.3;
+    }
+
+    Ok((hits, misses, dict_bytes, overhead))
+}
+
+/// Encodes a chunk of a layer with its dictionaries, or with larger, refined or preset dictionaries, or without
+/// any, whichever is smallest, and appends it to `buf`. Returns the bytes covered by hits and misses, and the
+/// bytes spent on dictionaries and token overhead.
+///
+/// The dictionaries are stored relative to the `previous` dictionaries where that is smaller, and replace them
+/// if the chunk keeps any.
+fn encode_layer_chunk(
+    chunk: &[u8],
+    chunk_dicts: &mut Vec<Dictionary>,
+    previous: &mut Vec<Dictionary>,
+    buf: &mut Vec<u8>,
+    options: &Options,
+    preset: &[Dictionary],
+) -> Result<(u64, u64, u64, u64)> {
+    let chunk_len = chunk.len() as u64;
+    let elem_bytes = chunk_dicts[0].elem_bytes;
+
+    // entries kept from the previous chunk keep their order, so the dictionaries can be stored as changes
+    dict_delta::align(chunk_dicts, previous);
+
+    // larger dictionaries can only pay off if the normal ones ran out of space
+    let try_ext = chunk_dicts.iter().any(|dict| dict.full());
+
+    let mut buf_chunk: Vec<u8> = vec![];
+    let (mut h, mut m, mut o) = compress_chunk(chunk_dicts, chunk, &mut buf_chunk, options)?;
+
+    if try_ext {
+        let mut ext_dicts: Vec<Dictionary> = (0..elem_bytes as u64)
+            .map(|alignment| generate_ext_dict(chunk, alignment, elem_bytes, options.min_occurrences))
+            .collect();
+
+        for dict in ext_dicts.iter_mut() {
+            dict.coverage = chunk_len;
+        }
+
+        let mut buf_ext: Vec<u8> = vec![];
+        let ext_result = compress_chunk(&mut ext_dicts, chunk, &mut buf_ext, options)?;
+
+        if buf_ext.len() < buf_chunk.len() {
+            if DEBUG {
+                println!("Using extended dictionaries: {} -> {} Bytes", buf_chunk.len(), buf_ext.len());
+            }
+
+            buf_chunk = buf_ext;
+            *chunk_dicts = ext_dicts;
+            h = ext_result.0;
+            m = ext_result.1;
+            o = ext_result.2;
+        }
+    }
+
+    if options.cost_select {
+        let selection = selection::refine(chunk_dicts.clone(), buf_chunk, chunk, options)?;
+        buf_chunk = selection.encoded;
+        *chunk_dicts = selection.dicts;
+    }
+
+    // explicitly selected dictionaries can follow the regions of a chunk, but every switch costs a token,
+    // also those the alignment implies in the pair encoding
+    if options.regions && !options.rans {
+        let mut buf_regions: Vec<u8> = vec![];
+        let region_result = regions::compress_chunk(chunk, elem_bytes, options.min_occurrences, &mut buf_regions)?;
+
+        if buf_regions.len() < buf_chunk.len() {
+            if DEBUG {
+                println!("Using region dictionaries: {} -> {} Bytes", buf_chunk.len(), buf_regions.len());
+            }
+
+            buf_chunk = buf_regions;
+            *chunk_dicts = region_result.0;
+            h = region_result.1;
+            m = region_result.2;
+            o = region_result.3;
+        }
+    }
+
+    // small chunks can do better with the preset dictionaries. They are kept whole, so the first chunk, or one
+    // after another that used them, stores them as the same as the previous dictionaries
+    if !preset.is_empty() {
+        let mut preset_dicts = preset.to_vec();
+
+        for dict in preset_dicts.iter_mut() {
+            dict.coverage = chunk_len;
+        }
+
+        let mut buf_preset: Vec<u8> = vec![];
+        let preset_result = encode_chunk_coded(&mut preset_dicts, chunk, &mut buf_preset, options, false)?;
+
+        let own_len = dict_delta::rewrite_chunk(buf_chunk.clone(), chunk_dicts, previous).len();
+        let preset_len = dict_delta::rewrite_chunk(buf_preset.clone(), &preset_dicts, previous).len();
+
+        if preset_len < own_len {
+            if DEBUG {
+                println!("Using preset dictionaries: {} -> {} Bytes", own_len, preset_len);
+            }
+
+            buf_chunk = buf_preset;
+            *chunk_dicts = preset_dicts;
+            h = preset_result.0;
+            m = preset_result.1;
+            o = preset_result.2;
+        }
+    }
+
+    let buf_chunk = dict_delta::rewrite_chunk(buf_chunk, chunk_dicts, previous);
+
+    // chunks the pairs do little for can be stored, or written as runs or coded bytes without dictionaries,
+    // which leaves the previous dictionaries to the next chunk
+    if let Some(buf_plain) = strategy::smaller_encoding(chunk, options, buf_chunk.len())? {
+        if DEBUG {
+            println!("Writing the chunk without dictionaries: {} -> {} Bytes", buf_chunk.len(), buf_plain.len());
+        }
+
+        buf.extend(buf_plain);
+        return Ok((0, chunk_len, 0, 0));
+    }
+
+    *previous = chunk_dicts.clone();
+
+    // the dictionary lengths and the bytes of each element
+    let dict_bytes = chunk_dicts
+        .iter()
+        .map(|dict| utility::dict_size(dict.len(), elem_bytes) as u64)
+        .sum::<u64>();
+
+    buf.extend(buf_chunk);
+    Ok((h, m, dict_bytes, o))
+}
+
+pub fn compress_chunk<W: Write>(
+    dicts: &mut [Dictionary],
+    chunk: &[u8],
+    writer: &mut W,
+    options: &Options,
 ) -> Result<(u64, u64, u64)> {
-    // init buffers
-    let mut rad_buf = [0u8; ELEM_BYTES];
+    encode_chunk_coded(dicts, chunk, writer, options, true)
+}
+
+/// Encodes the chunk with rANS, if that is on, or with the pair encoding if that is smaller. The token streams
+/// of rANS have no place for matches, runs or literals, so the pair encoding can still win. Without `purge`
+/// the dictionaries are kept whole, so dictionaries equal to the previous ones can be stored as such.
+fn encode_chunk_coded<W: Write>(
+    dicts: &mut [Dictionary],
+    chunk: &[u8],
+    writer: &mut W,
+    options: &Options,
+    purge: bool,
+) -> Result<(u64, u64, u64)> {
+    if !options.rans {
+        return encode_chunk_parsed(dicts, chunk, writer, options, purge);
+    }
+
+    let mut pairs_dicts = dicts.to_vec();
+    let mut pairs_buf: Vec<u8> = vec![];
+    let pairs_options = Options { rans: false, ..options.clone() };
+    let pairs = encode_chunk_parsed(&mut pairs_dicts, chunk, &mut pairs_buf, &pairs_options, purge)?;
+
+    let mut rans_buf: Vec<u8> = vec![];
+    let coded = encode_chunk_parsed(dicts, chunk, &mut rans_buf, options, purge)?;
+
+    if pairs_buf.len() < rans_buf.len() {
+        dicts.clone_from_slice(&pairs_dicts);
+        writer.write_all(&pairs_buf)?;
+        return Ok(pairs);
+    }
+
+    writer.write_all(&rans_buf)?;
+    Ok(coded)
+}
+
+/// Encodes the chunk with the better of the greedy and the optimal parse, if that is on.
+fn encode_chunk_parsed<W: Write>(
+    dicts: &mut [Dictionary],
+    chunk: &[u8],
+    writer: &mut W,
+    options: &Options,
+    purge: bool,
+) -> Result<(u64, u64, u64)> {
+    if !options.optimal_parse {
+        return encode_chunk(dicts, chunk, writer, options, false, purge);
+    }
+
+    // the optimal parse pays for every element it uses, so it can still lose to the greedy parse
+    let mut greedy_dicts = dicts.to_vec();
+    let mut greedy_buf: Vec<u8> = vec![];
+    let greedy = encode_chunk(&mut greedy_dicts, chunk, &mut greedy_buf, options, false, purge)?;
+
+    let mut optimal_buf: Vec<u8> = vec![];
+    let optimal = encode_chunk(dicts, chunk, &mut optimal_buf, options, true, purge)?;
+
+    if greedy_buf.len() < optimal_buf.len() {
+        dicts.clone_from_slice(&greedy_dicts);
+        writer.write_all(&greedy_buf)?;
+        return Ok(greedy);
+    }
+
+    writer.write_all(&optimal_buf)?;
+    Ok(optimal)
+}
+
+fn encode_chunk<W: Write>(
+    dicts: &mut [Dictionary],
+    chunk: &[u8],
+    writer: &mut W,
+    options: &Options,
+    optimal: bool,
+    purge: bool,
+) -> Result<(u64, u64, u64)> {
+    // encode the chunk once, counting the useage of the dictionary elements
+    let (mut tokens, hits, misses) = if optimal {
+        optimal_parse::parse(dicts, chunk)
+    } else {
+        tokenize_chunk(dicts, chunk, allowed_tokens(options))
+    };
+
+    //remove unused elements from dictionaries to save extra space, and move hits to the new indexes
+    if purge {
+        let remaps: Vec<Vec<u16>> = dicts.iter_mut().map(|dict| dict.purge_unused()).collect();
+        remap_hits(&mut tokens, &remaps);
+    }
+
+    if options.rans {
+        let wri_buf = rans::encode_tokens(&tokens, chunk);
+        write_to_comp_file(&wri_buf, writer, dicts, ChunkMethod::PairsRans, 0)?;
+
+        return Ok((hits, misses, 0));
+    }
+
+    let mut flags = tokens.iter().fold(0, |flags, token| match token {
+        Token::Match { .. } => flags | MATCHES,
+        Token::ByteRun { .. } | Token::HitRun(_) => flags | RUNS,
+        _ => flags,
+    });
+
     let mut wri_buf: Vec<u8> = vec![];
-    let mut hit_buf: Vec<u8> = vec![];
-    let mut mis_buf: Vec<u8> = vec![];
-    let mut res_buf: Vec<u8> = vec![];
+    let overhead = write_tokens(&mut wri_buf, &tokens, chunk, None);
+
+    // the missed bytes can also follow the tokens as a coded stream, kept if that takes fewer bytes
+    if options.literals {
+        let mut token_buf: Vec<u8> = vec![];
+        let mut literals: Vec<u8> = vec![];
+        write_tokens(&mut token_buf, &tokens, chunk, Some(&mut literals));
+
+        if !literals.is_empty() {
+            let mut separate: Vec<u8> = vec![];
+            utility::write_varint(&mut separate, token_buf.len() as u64);
+            separate.extend(token_buf);
+            separate.extend(rans::encode_literals(&literals));
+
+            if separate.len() < wri_buf.len() {
+                wri_buf = separate;
+                flags |= LITERALS;
+            }
+        }
+    }
+
+    write_to_comp_file(&wri_buf, writer, dicts, ChunkMethod::Pairs, flags)?;
+
+    Ok((hits, misses, overhead))
+}
+
+/// Parses the chunk greedily into hits and misses, and the other tokens `allowed` by the chunk flags.
+///
+/// With `RUNS`, long runs of a byte are taken first, and hits followed by enough repeats of their element
+/// are closed with a hit run. With `MATCHES`, earlier repeats of the bytes at each position are looked for
+/// next, and used when they take fewer bytes than hitting every element would.
+/// Flags of the tokens the greedy parse may use besides hits and misses. The token streams of rANS have no
+/// place for matches or runs.
+pub(crate) fn allowed_tokens(options: &Options) -> u8 {
+    let mut allowed = 0;
+
+    if options.matches && !options.rans {
+        allowed |= MATCHES;
+    }
+
+    if options.runs && !options.rans {
+        allowed |= RUNS;
+    }
+
+    allowed
+}
+
+pub(crate) fn tokenize_chunk(dicts: &mut [Dictionary], chunk: &[u8], allowed: u8) -> (Vec<Token>, u64, u64) {
+    // init buffers
+    let mut tokens: Vec<Token> = vec![];
+    let mut hit_buf: Vec<u16> = vec![];
+    // bytes to be written raw, these always end where the buffered hits start
+    let mut missed = 0..0;
+    let mut matcher = if allowed & MATCHES != 0 { Some(Matcher::new(chunk)) } else { None };
+    let runs = allowed & RUNS != 0;
 
     // init variables
     let mut index: usize = 0;
     let mut hits = 0u64;
     let mut misses = 0u64;
-    let mut overhead = 0u64;
-    let mut has_read = 0u64;
-    let to_read = dicts[0].coverage;
-
-    // get start pos for reader to reset in dry run
-    let start_pos = reader.stream_position()?;
-
-    // start working through the file
-    while has_read < to_read {
-        let left_to_read: usize = (to_read - has_read) as usize;
-
-        // if less remains than can be fed into the read buffer
-        if left_to_read < ELEM_BYTES {
-            if !dry {
-                res_buf = vec![0u8; left_to_read];
-                reader.read_exact(&mut res_buf)?;
-            }
+    let mut has_read = 0usize;
+    let elem_bytes = dicts[0].elem_bytes;
 
-            // we can not read any more bytes from this chunk, so break out of the while loop
-            break;
+    // start working through the chunk, until less remains than an element
+    while has_read + elem_bytes <= chunk.len() {
+        let run = if runs { run_len(&chunk[has_read..], 1) } else { 0 };
+
+        if run >= MIN_BYTE_RUN {
+            let (h, m) = close_hits(&mut tokens, &mut hit_buf, &mut missed, index, &mut dicts[index]);
+            hits += h + run as u64;
+            misses += m;
+
+            tokens.push(Token::ByteRun { byte: chunk[has_read], len: run });
+
+            // like missed bytes, the run moves the alignment along
+            has_read += run;
+            missed = has_read..has_read;
+            index = (index + run) % dicts.len();
+            continue;
+        }
+
+        let repeat = matcher.as_mut().and_then(|matcher| matcher.longest(has_read, MAX_MATCH));
+
+        if let Some((distance, len)) = repeat.filter(|(_, len)| worth_matching(*len, elem_bytes)) {
+            let (h, m) = close_hits(&mut tokens, &mut hit_buf, &mut missed, index, &mut dicts[index]);
+            hits += h + len as u64;
+            misses += m;
+
+            tokens.push(Token::Match { distance, len });
+
+            // like missed bytes, the repeated bytes move the alignment along
+            has_read += len;
+            missed = has_read..has_read;
+            index = (index + len) % dicts.len();
+            continue;
         }
 
-        reader.read_exact(&mut rad_buf)?;
-        has_read += ELEM_BYTES as u64;
+        let elem = &chunk[has_read..has_read + elem_bytes];
 
-        match dicts[index].get_index(&rad_buf) {
+        match dicts[index].get_index(elem) {
             // matched element in current dict
             Some(elem_index) => {
                 // add element index hits buf
-                hit_buf.push((1 << 7) | elem_index);
+                hit_buf.push(elem_index);
+                has_read += elem_bytes;
+
+                // repeats of the element are worth a hit run, even after a single hit
+                let repeats = if runs { run_len(&chunk[has_read - elem_bytes..], elem_bytes) - 1 } else { 0 };
+
+                if repeats >= MIN_HIT_RUN {
+                    hits += push_hits(&mut tokens, &mut hit_buf, &mut missed, index, &mut dicts[index]);
+                    hits += (repeats * elem_bytes) as u64;
+                    tokens.push(Token::HitRun(repeats));
+
+                    has_read += repeats * elem_bytes;
+                    missed = has_read..has_read;
+                }
             }
 
             // did not match element in current dict
             None => {
-                let (h, m, o) = manage_hits(dry, &mut wri_buf, &mut hit_buf, &mut mis_buf, dicts[index]);
+                let (h, m) = manage_hits(&mut tokens, &mut hit_buf, &mut missed, index, &mut dicts[index]);
                 hits += h;
                 misses += m;
-                overhead += o;
-
-                if !dry && DETAILED_DEBUG && h > 0 {
-                    println!("Writing {} bytes from dictionary {}", h / 2, index);
-                }
 
-                reader.seek_relative(-(ELEM_HALF as i64))?;
-                has_read -= ELEM_HALF as u64;
-                mis_buf.extend(&rad_buf[0..ELEM_HALF]);
-                misses += ELEM_HALF as u64;
-                index = if index == 0 { 1 } else { 0 };
+                // miss a single byte, which moves on to the dictionary of the next alignment
+                has_read += 1;
+                missed.end = has_read;
+                misses += 1;
+                index = (index + 1) % dicts.len();
             }
         }
     }
 
-    let (h, m, o) = manage_hits(dry, &mut wri_buf, &mut hit_buf, &mut mis_buf, dicts[index]);
+    let (h, m) = manage_hits(&mut tokens, &mut hit_buf, &mut missed, index, &mut dicts[index]);
     hits += h;
     misses += m;
-    overhead += o;
 
-    if !dry && DETAILED_DEBUG && h > 0 {
-        println!("Writing {} bytes from dictionary: {}", h / 2, index);
+    // add any elements in end of the chunk to the missed bytes
+    missed.end = chunk.len();
+    if missed.start != missed.end {
+        tokens.push(Token::Miss(missed));
     }
 
-    if !dry {
-        mis_buf.extend(&res_buf); // add any elements in end of the chunk to buffered misses
-        write_missed(&mut wri_buf, &mut mis_buf);
-        write_to_comp_file(&wri_buf, writer, dicts[0], dicts[1])?;
-    } else if dry {
-        reader.seek(SeekFrom::Start(start_pos))?;
+    (tokens, hits, misses)
+}
+
+/// Elements of `elem_bytes` at the start of `data` equal to the first one.
+fn run_len(data: &[u8], elem_bytes: usize) -> usize {
+    let first = &data[..elem_bytes];
+    data.chunks_exact(elem_bytes).take_while(|elem| *elem == first).count()
+}
+
+/// Registers the buffered hits and writes out the missed bytes, before a token which is neither.
+fn close_hits(
+    tokens: &mut Vec<Token>,
+    buf_hits: &mut Vec<u16>,
+    missed: &mut Range<usize>,
+    dict_index: usize,
+    dict: &mut Dictionary,
+) -> (u64, u64) {
+    let counts = manage_hits(tokens, buf_hits, missed, dict_index, dict);
+
+    if missed.start != missed.end {
+        tokens.push(Token::Miss(missed.clone()));
     }
 
-    Ok((hits, misses, overhead))
+    counts
+}
+
+/// A match of `len` bytes pays off if it takes fewer bytes than the hits of all its elements.
+pub(crate) fn worth_matching(len: usize, elem_bytes: usize) -> bool {
+    len >= MIN_MATCH && match_size(len) * elem_bytes < len
 }
 
-fn manage_hits(
-    dry_run: bool,
-    buf_write: &mut Vec<u8>,
-    buf_hits: &mut Vec<u8>,
-    buf_missed: &mut Vec<u8>,
+pub(crate) fn match_size(len: usize) -> usize {
+    if len - MIN_MATCH < 7 {
+        4
+    } else {
+        6
+    }
+}
+
+pub(crate) fn manage_hits(
+    tokens: &mut Vec<Token>,
+    buf_hits: &mut Vec<u16>,
+    missed: &mut Range<usize>,
+    dict_index: usize,
     dict: &mut Dictionary,
-) -> (u64, u64, u64) {
+) -> (u64, u64) {
     let hits_len = buf_hits.len() as u64;
 
-    let mut hits = 0u64;
+    // if there are hits to be registered as tokens
+    if hits_len > 1 {
+        return (push_hits(tokens, buf_hits, missed, dict_index, dict), 0);
+    }
+
     let mut misses = 0u64;
-    let mut overhead = 0u64;
 
-    // if there are hits to be registered to the write buffer
-    if hits_len > 1 {
-        hits = hits_len * 2;
-        if dry_run {
-            increment_useages(buf_hits, dict);
-        } else {
-            overhead = write_missed(buf_write, buf_missed);
-            buf_write.extend(buf_hits.as_slice());
+    // otherwise the hits should be counted as misses instead to minimise overhead
+    if hits_len > 0 {
+        misses = hits_len * dict.elem_bytes as u64;
+        missed.end += buf_hits.len() * dict.elem_bytes;
+    }
+
+    buf_hits.clear();
+    (0, misses)
+}
+
+/// Registers the buffered hits as tokens after the missed bytes, returning the bytes they cover.
+fn push_hits(
+    tokens: &mut Vec<Token>,
+    buf_hits: &mut Vec<u16>,
+    missed: &mut Range<usize>,
+    dict_index: usize,
+    dict: &mut Dictionary,
+) -> u64 {
+    let hit_bytes = buf_hits.len() * dict.elem_bytes;
+    let hits_end = missed.end + hit_bytes;
+
+    if missed.start != missed.end {
+        tokens.push(Token::Miss(missed.clone()));
+    }
+
+    for index in buf_hits.iter() {
+        dict.increment_usage(*index);
+        tokens.push(Token::Hit {
+            dict: dict_index as u8,
+            index: *index,
+        });
+    }
+
+    *missed = hits_end..hits_end;
+    buf_hits.clear();
+
+    hit_bytes as u64
+}
+
+pub(crate) fn remap_hits(tokens: &mut [Token], remaps: &[Vec<u16>]) {
+    for token in tokens.iter_mut() {
+        if let Token::Hit { dict, index } = token {
+            *index = remaps[*dict as usize][*index as usize];
         }
+    }
+}
 
-        buf_missed.clear();
-        buf_hits.clear();
+/// Writes the tokens of a chunk, with the missed bytes after their miss tokens or else collected in `literals`.
+fn write_tokens(buf_write: &mut Vec<u8>, tokens: &[Token], chunk: &[u8], mut literals: Option<&mut Vec<u8>>) -> u64 {
+    let mut overhead = 0;
+
+    for token in tokens {
+        match token {
+            Token::Hit { index, .. } => overhead += write_hit(buf_write, *index as usize),
+            Token::Miss(range) => match literals.as_deref_mut() {
+                Some(literals) => {
+                    overhead += write_miss_len(buf_write, range.len());
+                    literals.extend_from_slice(&chunk[range.clone()]);
+                }
+                None => overhead += write_missed(buf_write, &chunk[range.clone()]),
+            },
+            Token::Match { distance, len } => overhead += write_match(buf_write, *distance, *len),
+            Token::ByteRun { byte, len } => {
+                overhead += write_run(buf_write, BYTE_RUN_MARK, len - MIN_BYTE_RUN);
+                buf_write.push(*byte);
+                overhead += 1;
+            }
+            Token::HitRun(repeats) => overhead += write_run(buf_write, HIT_RUN_MARK, repeats - MIN_HIT_RUN),
+        }
     }
 
-    // otherwise the hits should be counted as misses instead to minimise overhead
-    else if hits_len > 0 {
-        misses = hits_len * 2;
-        concatinate_hits_to_misses(buf_missed, buf_hits, dict);
-        buf_hits.clear();
+    overhead
+}
+
+fn write_run(buf_write: &mut Vec<u8>, mark: u8, extra: usize) -> u64 {
+    if extra < 15 {
+        buf_write.push(mark | extra as u8);
+        1
+    } else {
+        buf_write.push(mark | 15);
+        buf_write.extend(&((extra - 15) as u32).to_be_bytes()[1..]);
+        4
     }
+}
+
+fn write_match(buf_write: &mut Vec<u8>, distance: usize, len: usize) -> u64 {
+    let extra = len - MIN_MATCH;
 
-    (hits, misses, overhead)
+    if extra < 7 {
+        buf_write.push(MATCH_MARK | extra as u8);
+    } else {
+        buf_write.push(MATCH_MARK | 7);
+        buf_write.extend(((extra - 7) as u16).to_be_bytes());
+    }
+
+    buf_write.extend(&(distance as u32).to_be_bytes()[1..]);
+    match_size(len) as u64
 }
 
-fn increment_useages(buf_hits: &[u8], dict: &mut Dictionary) {
-    for i in 0..buf_hits.len() {
-        dict.increment_usage(buf_hits[i] & 0b01111111);
+pub(crate) fn write_hit(buf_write: &mut Vec<u8>, index: usize) -> u64 {
+    if index < VALUES {
+        buf_write.push((1 << 7) | index as u8);
+        0
+    }
+    // indexes outside of the one byte hits use an extra byte
+    else {
+        let ext_index = index - VALUES;
+        buf_write.push(EXT_HIT_MARK | (ext_index >> 8) as u8);
+        buf_write.push(ext_index as u8);
+        1
     }
 }
 
-fn concatinate_hits_to_misses(buf_missed: &mut Vec<u8>, buf_hits: &[u8], dict: &Dictionary) {
-    for i in 0..buf_hits.len() {
-        let raw_data = dict.get(buf_hits[i] & 0b01111111);
-        buf_missed.extend(&raw_data);
+pub(crate) fn write_missed(buf_write: &mut Vec<u8>, buf_missed: &[u8]) -> u64 {
+    let overhead = write_miss_len(buf_write, buf_missed.len());
+
+    if DETAILED_DEBUG {
+        println!("Writing missed {} Byte(s) {:?}", buf_missed.len(), buf_missed);
     }
+
+    buf_write.extend(buf_missed.to_vec());
+    overhead
 }
 
-fn write_missed(buf_write: &mut Vec<u8>, buf_missed: &[u8]) -> u64 {
-    let missed = buf_missed.len();
+/// Writes the token of a miss of `missed` bytes, without the bytes themselves.
+fn write_miss_len(buf_write: &mut Vec<u8>, missed: usize) -> u64 {
     let mut overhead = 0;
 
     // if a lot of raw values needs to be written first
     if missed >= VALUES_HALF {
-        let nr_bytes = utility::bytes_to_rep(missed);
-        let bytes = utility::val_to_u8_vec(missed, nr_bytes);
-        buf_write.push(nr_bytes);
-        buf_write.extend(&bytes);
-        overhead += (1 + bytes.len()) as u64;
+        let extra = missed - VALUES_HALF;
+        let start = buf_write.len();
+        buf_write.push((extra & ((1 << LONG_MISS_BITS) - 1)) as u8);
+        utility::write_varint(buf_write, (extra >> LONG_MISS_BITS) as u64);
+        overhead += (buf_write.len() - start) as u64;
     }
     // if only a few raw values needs to be written first
     else if missed > 0 {
@@ -299,35 +983,31 @@ fn write_missed(buf_write: &mut Vec<u8>, buf_missed: &[u8]) -> u64 {
         overhead += 1;
     }
 
-    if DETAILED_DEBUG {
-        println!("Writing missed {} Byte(s) {:?}", buf_missed.len(), buf_missed);
-    }
-
-    buf_write.extend(buf_missed.to_vec());
     overhead
 }
 
-fn get_path_and_writer(path: &Path) -> Result<(PathBuf, BufWriter<File>)> {
-    let f_ex = path.extension().unwrap().to_str().unwrap();
-    let is_tmp = f_ex.find("tmp") != None;
-    let f_st: &str;
+pub(crate) fn get_path_and_writer(path: &Path) -> Result<(PathBuf, BufWriter<File>)> {
+    let f_ex = path.extension().and_then(|ex| ex.to_str()).unwrap_or("");
+    let is_tmp = f_ex.contains("tmp");
 
     let mut end_nr = 1;
 
-    if is_tmp {
+    let f_st = if is_tmp {
         end_nr += f_ex.split_at(3).1.parse::<u32>().unwrap();
-        f_st = path.file_stem().unwrap().to_str().unwrap();
+        path.file_stem().unwrap().to_str().unwrap()
     } else {
-        f_st = path.to_str().unwrap();
-    }
+        path.file_name().unwrap().to_str().unwrap()
+    };
 
-    let path_comp = PathBuf::from(format!("{}.tmp{}", f_st, end_nr));
+    // keep the temporary files next to the original file
+    let path_comp = path.with_file_name(format!("{}.tmp{}", f_st, end_nr));
 
     let file = OpenOptions::new()
         .write(true)
         .append(false)
         .read(true)
         .create(true)
+        .truncate(true)
         .open(&path_comp)?;
 
     let writer = BufWriter::new(file);
@@ -335,34 +1015,37 @@ fn get_path_and_writer(path: &Path) -> Result<(PathBuf, BufWriter<File>)> {
     Ok((path_comp, writer))
 }
 
-fn write_to_comp_file(
+fn write_to_comp_file<W: Write>(
     buf_write: &[u8],
-    writer: &mut Writer,
-    dict_eve: &Dictionary,
-    dict_odd: &Dictionary,
+    writer: &mut W,
+    dicts: &[Dictionary],
+    method: ChunkMethod,
+    flags: u8,
 ) -> Result<()> {
     let mut buf_final: Vec<u8> = vec![];
 
-    // add dictionary pair to file
-    buf_final.push(dict_eve.len());
-    buf_final.extend(dict_eve.to_vec());
-    buf_final.push(dict_odd.len());
-    buf_final.extend(dict_odd.to_vec());
-
-
+    // add the dictionary of each alignment to file
+    for dict in dicts {
+        write_dict(&mut buf_final, dict);
+    }
 
     // move buf_write data to buf_final
     buf_final.extend(buf_write);
 
-    // add buf_write length to out file as 4 bytes
-    let bytes = 4;
-    let len = bytes + buf_final.len();
-    let chunk_len_buf = utility::val_to_u8_vec(len, bytes as u8);
+    write_chunk(writer, method as u8 | flags, &buf_final)
+}
+
+/// Writes a chunk as the length of the rest in a varint, the method byte with any flags and the payload.
+pub(crate) fn write_chunk<W: Write>(writer: &mut W, method_byte: u8, payload: &[u8]) -> Result<()> {
+    let mut chunk_head: Vec<u8> = vec![];
+    utility::write_varint(&mut chunk_head, 1 + payload.len() as u64);
+    chunk_head.push(method_byte);
+    let len = chunk_head.len() + payload.len();
 
-    writer.write_all(&chunk_len_buf)?;
+    writer.write_all(&chunk_head)?;
 
-    // add buf_write content to out file
-    writer.write_all(&buf_final)?;
+    // add payload content to out file
+    writer.write_all(payload)?;
 
     if DETAILED_DEBUG {
         println!("\nWriting chunk of length {} Bytes to file.\nRaw chunk data:", len);
@@ -370,8 +1053,8 @@ fn write_to_comp_file(
         let data_per_line = 12;
         let mut data_in_line = 0;
 
-        data_in_line = utility::print_chunk_vec(chunk_len_buf, data_per_line, data_in_line);
-        utility::print_chunk_vec(buf_final.to_vec(), data_per_line, data_in_line);
+        data_in_line = utility::print_chunk_vec(chunk_head, data_per_line, data_in_line);
+        utility::print_chunk_vec(payload.to_vec(), data_per_line, data_in_line);
 
         println!("\n");
     }
@@ -379,70 +1062,85 @@ fn write_to_comp_file(
     Ok(())
 }
 
+pub(crate) fn write_dict(buf_final: &mut Vec<u8>, dict: &Dictionary) {
+    let len = dict.len();
+
+    if len > VALUES {
+        buf_final.push(EXT_DICT_LEN);
+        buf_final.extend(utility::val_to_u8_vec(len, 2));
+    } else {
+        buf_final.push(len as u8);
+    }
+
+    buf_final.extend(dict.to_vec());
+}
+
 fn print_comp_result(
-    dictionaries: &[(Dictionary, Dictionary)],
-    path: &Path,
+    dictionaries: &[Vec<Dictionary>],
+    size_before: u64,
     path_comp: &Path,
     hit_data: u64,
     miss_data: u64,
     dict_data: u64,
     overhead_data: u64,
 ) -> Result<()> {
-    let size_before = path.metadata()?.len();
     let size_after = path_comp.metadata()?.len();
 
-    if !(size_after < size_before) {
+    if size_after >= size_before {
         return Ok(());
     }
 
     println!("\nLAYER RESULT:\n{} Bytes -> {} Bytes", size_before, size_after);
     println!("COMPRESSED: {} Bytes. NON-COMPRESSED: {} Bytes. DICTIONARIES: {} Bytes, OVERHEAD: {} Bytes",
-        hit_data / 2,
+        hit_data,
         miss_data,
         dict_data,
         overhead_data,
     );
 
     if DEBUG_DICT {
-        for dict in dictionaries {
-            println!(
-                "Dict 1: {}\nDict 2: {}\n",
-                dict.0.to_string(),
-                dict.1.to_string()
-            );
+        for chunk_dicts in dictionaries {
+            for (index, dict) in chunk_dicts.iter().enumerate() {
+                println!("Dict {}: {}", index + 1, dict);
+            }
+            println!();
         }
     }
 
     Ok(())
 }
 
-fn finalize_file(path: &Path, layers: u8) -> Result<PathBuf> {
+fn finalize_file(path: &Path, layer: &[u8], header: &Header) -> Result<PathBuf> {
     let (final_path, mut writer) = get_final_writer(path)?;
-    let mut file = File::open(path)?;
-    let mut buf: Vec<u8> = vec![layers];
 
-    // load file into buf
-    file.read_to_end(&mut buf)?;
+    // only keep the Huffman stage if it makes the file smaller
+    let mut header = header.clone();
+    let coded = if header.huffman { huffman::encode(layer) } else { vec![] };
+    header.huffman = header.huffman && coded.len() < layer.len();
+
+    let mut buf: Vec<u8> = header.to_vec()?;
+    buf.extend(if header.huffman { &coded } else { layer });
+
     writer.write_all(&buf)?;
     writer.flush()?;
 
     if DEBUG {
-        println!("\nFinal file: {} bytes with {} layers", buf.len(), layers);
+        println!("\nFinal file: {} bytes with {} layers", buf.len(), header.layers);
     }
 
     Ok(final_path)
 }
 
 fn get_final_writer(path: &Path) -> Result<(PathBuf, BufWriter<File>)> {
-    let extension = path.extension().unwrap().to_str().unwrap();
+    let extension = path.extension().and_then(|ex| ex.to_str()).unwrap_or("");
     // if the path has a tmp extension, remove the tmp extension, otherwise keep the file as is
-    let stem = if extension.find("tmp") != None {
+    let stem = if extension.contains("tmp") {
         path.file_stem().unwrap().to_str().unwrap()
     } else {
-        path.to_str().unwrap()
+        path.file_name().unwrap().to_str().unwrap()
     };
 
-    let path_final = PathBuf::from(format!("{}.lc", stem));
+    let path_final = path.with_file_name(format!("{}.lc", stem));
 
     // remove compressed file if it already exists
     if path_final.exists() {
@@ -454,6 +1152,7 @@ fn get_final_writer(path: &Path) -> Result<(PathBuf, BufWriter<File>)> {
         .append(false)
         .read(true)
         .create(true)
+        .truncate(true)
         .open(&path_final)?;
 
     let writer = BufWriter::new(file);
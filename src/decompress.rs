@@ -1,99 +1,87 @@
+use std::convert::TryInto;
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
-use std::io::{BufReader, BufWriter, Result, SeekFrom};
+use std::io::{BufWriter, Error, ErrorKind, Result};
 use std::path::{Path, PathBuf};
 
+use crate::comp_structs::{
+    chunk_method::{ChunkMethod, DELTA_DICTS, LITERALS, MATCHES, RUNS},
+    dict_elem::DictElem,
+    dictionary::Dictionary,
+    header::Header,
+};
+use crate::bwt;
+use crate::dict_delta;
+use crate::filter;
+use crate::huffman;
+use crate::input::Input;
+use crate::preset::Preset;
+use crate::rans;
+use crate::repair;
+use crate::strategy;
 use crate::utility;
-use crate::utility::{Reader, Writer, DEBUG, DETAILED_DEBUG, DEBUG_DICT, ELEM_BYTES};
+use crate::utility::{
+    BYTE_RUN_MARK, CHUNK_MAX_SIZE, DEBUG, DETAILED_DEBUG, DEBUG_DICT, EXT_DICT_LEN, EXT_HIT_MARK, HIT_RUN_MARK,
+    LONG_MISS_BITS, MATCH_MARK, MIN_BYTE_RUN, MIN_HIT_RUN, MIN_MATCH, SELECT_MARK, VALUES, VALUES_HALF,
+};
 
-struct DictElem {
-    data: [u8; ELEM_BYTES],
+pub fn run(path: &Path) -> Result<PathBuf> {
+    run_with_preset(path, None)
 }
 
-impl DictElem {
-    pub fn new(slice: [u8; ELEM_BYTES]) -> Self {
-        DictElem { data: slice }
-    }
-
-    pub fn to_string(&self) -> String {
-        let mut out: String = String::from("[");
-        for i in 0..ELEM_BYTES {
-            out.push_str(utility::u8_to_string(self.data[i]).as_str());
-            // if not last value add a comma after written val
-            if i < ELEM_BYTES - 1 {
-                out.push_str(", ");
-            }
-        }
-        out.push_str("]");
-
-        out
-    }
-}
-
-struct Dictionary {
-    elems: Vec<DictElem>,
-}
-
-impl Dictionary {
-    pub fn new() -> Self {
-        Dictionary { elems: vec![] }
-    }
-
-    pub fn push(&mut self, elem: DictElem) {
-        self.elems.push(elem);
-    }
-
-    pub fn get(&self, index: u8) -> [u8; ELEM_BYTES] {
-        self.elems[index as usize].data
-    }
-
-    pub fn get_dict_elem(&self, index: u8) -> &DictElem {
-        &self.elems[index as usize]
-    }
-
-    pub fn len(&self) -> usize {
-        self.elems.len()
-    }
-
-    pub fn to_string(&self) -> String {
-        let mut out = String::from(format!("Elements: {}", self.elems.len()));
-
-        for i in 0..self.elems.len() {
-            out.push_str(format!("\nElem {}: {}", i, self.elems[i].to_string()).as_str());
-        }
-
-        out
-    }
-}
-
-pub fn run(path: &PathBuf) -> Result<PathBuf> {
-    println!("Decompressing file {}", path.file_name().unwrap().to_str().unwrap());
-    let path_uncomp = decompress(path)?;
+/// Decompresses a file, which needs `preset` if it was compressed with one.
+pub fn run_with_preset(path: &Path, preset: Option<&Preset>) -> Result<PathBuf> {
+    let path_uncomp = decompress(path, preset)?;
     Ok(path_uncomp)
 }
 
-fn decompress(path: &Path) -> Result<PathBuf> {
-    let mut reader = BufReader::new(File::open(path)?);
+fn decompress(path: &Path, preset: Option<&Preset>) -> Result<PathBuf> {
+    let mut input = Input::open(path)?;
     let mut old_path = path.to_path_buf();
-    let layers = get_layers(&mut reader)?;
+    let (header, mut start) = Header::read(&input)?;
+    let layers = header.layers;
+
+    // files without a preset are decoded the same with or without one
+    let preset = match (header.preset, preset) {
+        (None, _) => None,
+        (Some(id), Some(preset)) if preset.id() == id => Some(preset),
+        (Some(_), Some(_)) => {
+            return Err(Error::new(ErrorKind::InvalidInput, "file was compressed with a different preset"));
+        }
+        (Some(_), None) => return Err(Error::new(ErrorKind::InvalidInput, "file needs a preset to decompress")),
+    };
 
     if DEBUG {
         println!("\nDecompressing {} layers\n", layers);
     }
 
-    // if there is no compression, just read file into output to remove added byte in beginning
-    if layers == 0 {
+    // undo the Huffman stage before the top layer is read
+    if header.huffman {
+        let (out, mut writer) = get_path_and_writer(path)?;
+        huffman::decode(&input[start..], &mut writer)?;
+        writer.flush()?;
+        drop(writer);
+
+        input = Input::open(&out)?;
+        start = 0;
+        old_path = out;
+    }
+
+    // if there is no compression, just write file into output to remove the header in the beginning
+    if layers == 0 && !header.huffman {
         let (out, mut writer) = get_path_and_writer(path)?;
-        let mut buf = vec![];
-        reader.read_to_end(&mut buf)?;
-        writer.write_all(&buf)?;
+        writer.write_all(&input[start..])?;
         writer.flush()?;
         old_path = out;
     }
 
     for layer in 0..layers {
-        let new_path = decompress_layer(&old_path, &mut reader)?;
-        reader = BufReader::new(File::open(&new_path)?);
+        // the top layer comes first, while presets count their layers from the bottom
+        let preset_dicts = preset.map_or(&[][..], |preset| preset.layer((layers - layer - 1) as usize));
+
+        let new_path = decompress_layer(&old_path, &input[start..], &header, preset_dicts)?;
+        input = Input::open(&new_path)?;
+        start = 0;
 
         if DEBUG {
             let old_l = old_path.metadata()?.len();
@@ -101,7 +89,7 @@ fn decompress(path: &Path) -> Result<PathBuf> {
             println!("Decompressed  layer {}  {} Bytes -> {} Bytes\n", layers - layer, old_l, new_l);
         }
 
-        if layer > 0 {
+        if old_path != path {
             // remove extra files that are finished
             std::fs::remove_file(&old_path)?;
         }
@@ -109,145 +97,405 @@ fn decompress(path: &Path) -> Result<PathBuf> {
         old_path = new_path;
     }
 
+    // release the last layer before the file is moved
+    drop(input);
+
+    if !header.filters.is_empty() {
+        let (out, mut writer) = get_path_and_writer(&old_path)?;
+        writer.write_all(&filter::revert_all(&header.filters, &Input::open(&old_path)?))?;
+        writer.flush()?;
+
+        if old_path != path {
+            std::fs::remove_file(&old_path)?;
+        }
+
+        old_path = out;
+    }
+
     let final_path = finalize_file(&old_path)?;
     Ok(final_path)
 }
 
-fn get_layers(reader: &mut Reader) -> Result<u8> {
-    let mut layer_slice = [0u8];
-    reader.read_exact(&mut layer_slice)?;
+pub(crate) fn read_bytes<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    if data.len() - *pos < len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "compressed data ended unexpectedly"));
+    }
+
+    let bytes = &data[*pos..*pos + len];
+    *pos += len;
 
-    Ok(layer_slice[0])
+    Ok(bytes)
 }
 
-fn decompress_layer(path: &Path, reader: &mut Reader) -> Result<PathBuf> {
-    // get curent pos
-    let current = reader.seek(SeekFrom::Current(0))?;
-    // get layer bytes (if on layer 0 then current is > 0)
-    let bytes_in_layer = reader.seek(SeekFrom::End(0))? - current;
-    // reset to current pos
-    reader.seek(SeekFrom::Start(current))?;
+/// Reads a varint written by `utility::write_varint`.
+pub(crate) fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = read_bytes(data, pos, 1)?[0];
+        value |= ((byte & 0x7F) as u64) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(Error::new(ErrorKind::InvalidData, "varint longer than 64 bits"))
+}
+
+fn decompress_layer(path: &Path, data: &[u8], header: &Header, preset: &[Dictionary]) -> Result<PathBuf> {
+    let bytes_in_layer = data.len();
 
     if DEBUG {
         println!("Decompressing layer of length {} Bytes", bytes_in_layer);
     }
 
-    // set read bytes to current pos in file
-    let mut bytes_read = current;
+    let mut bytes_read = 0;
     let (out, mut writer) = get_path_and_writer(path)?;
+    let mut previous: Vec<Dictionary> = preset.to_vec();
 
     while bytes_read < bytes_in_layer {
-        bytes_read += decompress_chunk(&mut writer, reader)?;
+        bytes_read += decompress_chunk(&mut writer, &data[bytes_read..], header, &mut previous)?;
     }
 
     writer.flush()?;
     Ok(out)
 }
 
-fn decompress_chunk(writer: &mut Writer, reader: &mut Reader) -> Result<u64> {
-    let mut dicts: Vec<Dictionary> = Vec::new();
-    let mut buf_chunk_total = [0u8; 4];
-
-    reader.read_exact(&mut buf_chunk_total)?;
-    let chunk_total = utility::u8_vec_to_u32(&buf_chunk_total) as u64;
+/// Decodes the chunk at the start of `data`, returning its length. `previous` holds the dictionaries of the
+/// previous chunk of the layer, which chunks can store their own relative to, and is replaced by this chunk's.
+pub fn decompress_chunk<W: Write>(
+    writer: &mut W,
+    data: &[u8],
+    header: &Header,
+    previous: &mut Vec<Dictionary>,
+) -> Result<usize> {
+    let mut pos = 0;
+
+    let chunk_total = if header.has_varints() {
+        let rest = read_varint(data, &mut pos)? as usize;
+        rest.checked_add(pos).ok_or_else(|| Error::new(ErrorKind::InvalidData, "chunk length out of range"))?
+    } else {
+        let buf_chunk_total = read_bytes(data, &mut pos, 4)?;
+        utility::u8_vec_to_u32(buf_chunk_total.try_into().unwrap()) as usize
+    };
 
     if DEBUG {
         println!("Decompressing chunk of length {} Bytes", chunk_total);
     }
 
-    if DETAILED_DEBUG {
-        let chunk_start = reader.seek(SeekFrom::Current(0))?;
-        let mut chunk_vec = vec![0u8; (chunk_total - 4) as usize];
-        reader.read(&mut chunk_vec)?;
-        reader.seek(SeekFrom::Start(chunk_start))?;
+    if chunk_total < pos {
+        return Err(Error::new(ErrorKind::InvalidData, "chunk shorter than its length prefix"));
+    }
+
+    // only parse the bytes belonging to this chunk
+    let mut chunk_pos = 0;
+    let chunk = read_bytes(data, &mut chunk_pos, chunk_total)?;
 
+    if DETAILED_DEBUG {
         println!("Raw chunk data:");
 
         let data_per_line = 12;
-        let mut data_in_line = 0;
-
-        data_in_line = utility::print_chunk_vec(buf_chunk_total.to_vec(), data_per_line, data_in_line);
-        utility::print_chunk_vec(chunk_vec, data_per_line, data_in_line);
+        utility::print_chunk_vec(chunk.to_vec(), data_per_line, 0);
         println!("\n");
     }
 
-    dicts.push(get_dictionary(reader)?);
-    dicts.push(get_dictionary(reader)?);
-    let dict_bytes = (2 + (2 * dicts[0].len()) + (2 * dicts[1].len())) as u64;
-    let chunk_length = chunk_total - dict_bytes - 4;
+    let method_byte = if header.has_chunk_method() {
+        read_bytes(chunk, &mut pos, 1)?[0]
+    } else {
+        ChunkMethod::Pairs as u8
+    };
 
-    if DEBUG_DICT {
-        println!(
-            "Dict 1: {}\nDict 2: {}\n\n",
-            dicts[0].to_string(),
-            dicts[1].to_string()
-        );
+    let method = ChunkMethod::from_u8(method_byte & !(DELTA_DICTS | MATCHES | RUNS | LITERALS))?;
+
+    if method == ChunkMethod::RePair {
+        repair::decompress_chunk(writer, &chunk[pos..])?;
+        previous.clear();
+        return Ok(chunk_total);
     }
 
-    let mut buf = [0u8];
+    if method == ChunkMethod::Bwt {
+        return decompress_bwt_chunk(writer, chunk, pos, header, previous);
+    }
+
+    // chunks without dictionaries leave those of the previous chunk for the next one
+    let plain = match method {
+        ChunkMethod::Stored => Some(writer.write_all(&chunk[pos..])),
+        ChunkMethod::Rle => Some(strategy::decode_rle(writer, &chunk[pos..])),
+        ChunkMethod::EntropyBytes => Some(strategy::decode_entropy_bytes(writer, &chunk[pos..])),
+        _ => None,
+    };
+
+    if let Some(result) = plain {
+        result?;
+        return Ok(chunk_total);
+    }
+
+    // select tokens are told apart from run tokens only by the chunk method
+    if method == ChunkMethod::PairsSelect && method_byte & RUNS != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "chunk with explicit dictionaries marked to have runs"));
+    }
+
+    let nr_dicts = if method == ChunkMethod::PairsSelect {
+        read_bytes(chunk, &mut pos, 1)?[0] as usize
+    } else {
+        header.elem_bytes
+    };
+
+    let dicts = if method_byte & DELTA_DICTS != 0 {
+        dict_delta::read_dictionaries(chunk, &mut pos, nr_dicts, header.elem_bytes, previous)?
+    } else {
+        get_dictionaries(chunk, &mut pos, nr_dicts, header.elem_bytes)?
+    };
+
+    match method {
+        ChunkMethod::PairsRans => rans::decode_tokens(writer, &dicts, &chunk[pos..])?,
+        _ => {
+            let explicit = method == ChunkMethod::PairsSelect;
+            let flags = method_byte & (MATCHES | RUNS | LITERALS);
+            decompress_pairs(writer, chunk, pos, &dicts, header, explicit, flags)?
+        }
+    }
+
+    *previous = dicts;
+
+    Ok(chunk_total)
+}
+
+/// Decodes the chunk of transformed bytes following the primary index, which has to fill the rest of the chunk,
+/// and reverts the transform.
+fn decompress_bwt_chunk<W: Write>(
+    writer: &mut W,
+    chunk: &[u8],
+    mut pos: usize,
+    header: &Header,
+    previous: &mut Vec<Dictionary>,
+) -> Result<usize> {
+    let primary = read_varint(chunk, &mut pos)?;
+    let inner = &chunk[pos..];
+
+    if primary > CHUNK_MAX_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "primary index out of range"));
+    }
+
+    // the transformed bytes are not transformed again, which also keeps damaged chunks from nesting deeply
+    let mut method_at = 0;
+    read_varint(inner, &mut method_at)?;
+
+    if read_bytes(inner, &mut method_at, 1)?[0] & !(DELTA_DICTS | MATCHES | RUNS | LITERALS) == ChunkMethod::Bwt as u8 {
+        return Err(Error::new(ErrorKind::InvalidData, "transformed chunk inside a transformed chunk"));
+    }
+
+    let mut transformed: Vec<u8> = vec![];
+
+    if decompress_chunk(&mut transformed, inner, header, previous)? != inner.len() {
+        return Err(Error::new(ErrorKind::InvalidData, "transformed chunk does not fill its chunk"));
+    }
+
+    writer.write_all(&bwt::decode(&transformed, primary as usize)?)?;
+
+    Ok(chunk.len())
+}
+
+/// Decodes hit and miss tokens. With `explicit` dictionaries select tokens choose between them, otherwise
+/// there is one for each alignment and misses move between them. The chunk `flags` tell if it also holds
+/// match tokens, repeating bytes decoded before, and run tokens, and if its missed bytes follow the tokens as a
+/// coded stream of literals.
+fn decompress_pairs<W: Write>(
+    writer: &mut W,
+    chunk: &[u8],
+    mut pos: usize,
+    dicts: &[Dictionary],
+    header: &Header,
+    explicit: bool,
+    flags: u8,
+) -> Result<()> {
+    // with literals the tokens end at the stream of missed bytes
+    let (chunk, mut literals) = if flags & LITERALS != 0 {
+        let token_len = read_varint(chunk, &mut pos)?;
+
+        if token_len > (chunk.len() - pos) as u64 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "compressed data ended unexpectedly"));
+        }
+
+        let tokens_end = pos + token_len as usize;
+        (&chunk[..tokens_end], Some(rans::LiteralReader::new(&chunk[tokens_end..])?))
+    } else {
+        (chunk, None)
+    };
+
+    let chunk_total = chunk.len();
+    let elem_bytes = header.elem_bytes;
     let mut dict_index = 0;
-    let mut read = 0;
+    // the decoded chunk, kept for matches to repeat from
+    let mut out: Vec<u8> = vec![];
+    let mut last_hit: Option<&[u8]> = None;
 
-    while read < chunk_length {
+    while pos < chunk_total {
         if DETAILED_DEBUG {
-            print! {"byte {}/{}: ", read, chunk_length};
+            print! {"byte {}/{}: ", pos, chunk_total};
+        }
+
+        let byte = read_bytes(chunk, &mut pos, 1)?[0];
+
+        if explicit && byte & 0b11100000 == SELECT_MARK {
+            dict_index = (byte & 0b00011111) as usize;
+
+            if DETAILED_DEBUG {
+                println! {"select dict {}", dict_index};
+            }
+
+            continue;
+        }
+
+        if flags & RUNS != 0 && byte & 0b11100000 == BYTE_RUN_MARK {
+            let count = read_run_count(chunk, &mut pos, byte)?;
+
+            if byte & 0b11110000 == HIT_RUN_MARK {
+                let elem = last_hit.ok_or_else(|| Error::new(ErrorKind::InvalidData, "run of hits before any hit"))?;
+
+                if DETAILED_DEBUG {
+                    println! {"{} more hits", count + MIN_HIT_RUN};
+                }
+
+                for _ in 0..count + MIN_HIT_RUN {
+                    out.extend_from_slice(elem);
+                }
+            } else {
+                let len = count + MIN_BYTE_RUN;
+                let value = read_bytes(chunk, &mut pos, 1)?[0];
+
+                if DETAILED_DEBUG {
+                    println! {"run of {} Bytes {}", len, value};
+                }
+
+                out.resize(out.len() + len, value);
+
+                // like missed bytes, the run shifts the alignment
+                if !explicit {
+                    dict_index = (dict_index + len % elem_bytes) % elem_bytes;
+                }
+            }
+
+            continue;
+        }
+
+        if flags & MATCHES != 0 && byte & 0b11111000 == MATCH_MARK {
+            let len = match byte & 0b00000111 {
+                7 => {
+                    let extra = read_bytes(chunk, &mut pos, 2)?;
+                    MIN_MATCH + 7 + u16::from_be_bytes([extra[0], extra[1]]) as usize
+                }
+                extra => MIN_MATCH + extra as usize,
+            };
+
+            let buf_distance = read_bytes(chunk, &mut pos, 3)?;
+            let distance = utility::u8_vec_to_u64(buf_distance) as usize;
+
+            if distance == 0 || distance > out.len() {
+                return Err(Error::new(ErrorKind::InvalidData, "match before the start of the chunk"));
+            }
+
+            if DETAILED_DEBUG {
+                println! {"match of {} Bytes from {} back", len, distance};
+            }
+
+            // the match can run into the bytes it writes, so copy them one by one
+            let start = out.len() - distance;
+            for i in start..start + len {
+                out.push(out[i]);
+            }
+
+            // like missed bytes, the repeated bytes shift the alignment
+            if !explicit {
+                dict_index = (dict_index + len % elem_bytes) % elem_bytes;
+            }
+
+            continue;
         }
 
-        reader.read_exact(&mut buf)?;
-        read += 1;
-        let byte = buf[0];
         let hit = ((byte >> 7) & 1) == 1;
 
-        if hit {
-            let index = byte & 0b01111111;
+        let is_ext_hit = !hit && byte & 0b11110000 == EXT_HIT_MARK;
+
+        if hit || is_ext_hit {
+            let index = if hit {
+                (byte & 0b01111111) as u16
+            } else {
+                let low = read_bytes(chunk, &mut pos, 1)?[0];
+                VALUES as u16 + ((((byte & 0b00001111) as u16) << 8) | low as u16)
+            };
+
+            if dict_index >= dicts.len() || index as usize >= dicts[dict_index].len() {
+                return Err(Error::new(ErrorKind::InvalidData, "hit outside of dictionary"));
+            }
+
             let dict_element = dicts[dict_index].get(index);
 
             if DETAILED_DEBUG {
-                println! {"index {} in dict {} {}", index, dict_index,  dicts[dict_index].get_dict_elem(index).to_string()};
+                println! {"index {} in dict {} {}", index, dict_index,  dicts[dict_index].get_elem(index)};
             }
 
-            writer.write_all(&dict_element)?;
+            out.extend_from_slice(dict_element);
+            last_hit = Some(dict_element);
         } else {
             let is_short = ((byte >> 6) & 1) == 1;
             let val_part = byte & 0b00111111;
 
             let miss_bytes: usize = if is_short {
                 val_part as usize
+            } else if header.has_varints() {
+                let high = read_varint(chunk, &mut pos)?;
+                let extra = (high << LONG_MISS_BITS) | (val_part & 0b111) as u64;
+                extra.saturating_add(VALUES_HALF as u64) as usize
             } else {
-                let mut buf_miss_bytes = vec![0u8; val_part as usize];
-
                 if DETAILED_DEBUG {
                     println! {"bytes to represent missed: {}", val_part};
                 }
 
-                reader.read_exact(&mut buf_miss_bytes)?;
-                read += val_part as u64;
-                utility::u8_vec_to_u64(&buf_miss_bytes) as usize
+                let buf_miss_bytes = read_bytes(chunk, &mut pos, val_part as usize)?;
+                utility::u8_vec_to_u64(buf_miss_bytes) as usize
             };
 
-            if (miss_bytes) % 2 == 1 {
-                dict_index = if dict_index == 0 { 1 } else { 0 };
+            // the missed bytes shift the alignment of the following elements
+            if !explicit {
+                dict_index = (dict_index + miss_bytes % elem_bytes) % elem_bytes;
             }
 
-            let mut buf_miss = vec![0u8; miss_bytes];
-            reader.read_exact(&mut buf_miss)?;
+            if let Some(literals) = literals.as_mut() {
+                if miss_bytes as u64 > CHUNK_MAX_SIZE {
+                    return Err(Error::new(ErrorKind::InvalidData, "miss longer than a chunk"));
+                }
+
+                literals.read(miss_bytes, &mut out)?;
+                continue;
+            }
+
+            let buf_miss = read_bytes(chunk, &mut pos, miss_bytes)?;
 
             if DETAILED_DEBUG {
                 println! {"missed {} Bytes: {:?}", miss_bytes, buf_miss};
             }
 
-            read += miss_bytes as u64;
-            writer.write_all(&buf_miss)?;
+            out.extend_from_slice(buf_miss);
         }
     }
 
-    Ok(chunk_total)
+    writer.write_all(&out)
+}
+
+/// Reads the count of a run token above the shortest run, held in its low 4 bits or the following 3 bytes.
+fn read_run_count(chunk: &[u8], pos: &mut usize, byte: u8) -> Result<usize> {
+    match byte & 0b00001111 {
+        15 => Ok(15 + utility::u8_vec_to_u64(read_bytes(chunk, pos, 3)?) as usize),
+        count => Ok(count as usize),
+    }
 }
 
 fn get_path_and_writer(path: &Path) -> Result<(PathBuf, BufWriter<File>)> {
     let f_st = path.file_stem().unwrap().to_str().unwrap();
     let f_ex = path.extension().unwrap().to_str().unwrap();
-    let is_tmp = f_ex.find("tmp") != None;
+    let is_tmp = f_ex.contains("tmp");
 
     let end_nr = 1 + if is_tmp {
         f_ex.split_at(3).1.parse::<u32>().unwrap()
@@ -255,7 +503,8 @@ fn get_path_and_writer(path: &Path) -> Result<(PathBuf, BufWriter<File>)> {
         0
     };
 
-    let path_comp = PathBuf::from(format!("{}.tmp{}", f_st, end_nr));
+    // keep the temporary files next to the compressed file
+    let path_comp = path.with_file_name(format!("{}.tmp{}", f_st, end_nr));
 
     if path_comp.exists() {
         std::fs::remove_file(&path_comp)?;
@@ -266,6 +515,7 @@ fn get_path_and_writer(path: &Path) -> Result<(PathBuf, BufWriter<File>)> {
         .append(false)
         .read(true)
         .create(true)
+        .truncate(true)
         .open(&path_comp)?;
 
     let writer = BufWriter::new(file);
@@ -273,17 +523,36 @@ fn get_path_and_writer(path: &Path) -> Result<(PathBuf, BufWriter<File>)> {
     Ok((path_comp, writer))
 }
 
-fn get_dictionary(reader: &mut Reader) -> Result<Dictionary> {
-    let mut dict = Dictionary::new();
-    let mut buf_short = [0u8];
-    let mut buf = [0u8; 2];
+/// Reads `nr_dicts` dictionaries, which without select tokens are one for each alignment of the elements.
+fn get_dictionaries(data: &[u8], pos: &mut usize, nr_dicts: usize, elem_bytes: usize) -> Result<Vec<Dictionary>> {
+    let mut dicts: Vec<Dictionary> = Vec::new();
 
-    reader.read_exact(&mut buf_short)?;
-    let len = buf_short[0];
+    for _ in 0..nr_dicts {
+        dicts.push(get_dictionary(data, pos, elem_bytes)?);
+    }
+
+    if DEBUG_DICT {
+        for (index, dict) in dicts.iter().enumerate() {
+            println!("Dict {}: {}", index + 1, dict);
+        }
+        println!("\n");
+    }
+
+    Ok(dicts)
+}
+
+pub(crate) fn get_dictionary(data: &[u8], pos: &mut usize, elem_bytes: usize) -> Result<Dictionary> {
+    let mut dict = Dictionary::with_elem_bytes(elem_bytes);
+
+    let mut len = read_bytes(data, pos, 1)?[0] as usize;
+
+    // larger dictionaries store their length in the following 2 bytes
+    if len == EXT_DICT_LEN as usize {
+        len = utility::u8_vec_to_u64(read_bytes(data, pos, 2)?) as usize;
+    }
 
     for _ in 0..len {
-        reader.read_exact(&mut buf)?;
-        let elem = DictElem::new(buf);
+        let elem = DictElem::new(read_bytes(data, pos, elem_bytes)?, 0);
         dict.push(elem);
     }
 
@@ -291,18 +560,22 @@ fn get_dictionary(reader: &mut Reader) -> Result<Dictionary> {
 }
 
 fn finalize_file(path: &Path) -> Result<PathBuf> {
-    let path_wo_tmp = Path::new(path.file_stem().unwrap());
+    let path_wo_tmp = path.with_file_name(path.file_stem().unwrap());
     let stem = path_wo_tmp.file_stem().unwrap().to_str().unwrap();
-    let extn = path_wo_tmp.extension().unwrap().to_str().unwrap();
 
     let path_final = if path_wo_tmp.exists() {
         let mut s = String::new();
         s.push_str(stem);
-        s.push_str("_decompressed.");
-        s.push_str(extn);
-        PathBuf::from(s)
+        s.push_str("_decompressed");
+
+        if let Some(extn) = path_wo_tmp.extension() {
+            s.push('.');
+            s.push_str(extn.to_str().unwrap());
+        }
+
+        path_wo_tmp.with_file_name(s)
     } else {
-        PathBuf::from(path_wo_tmp)
+        path_wo_tmp
     };
 
     std::fs::rename(path, &path_final)?;
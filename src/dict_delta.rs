@@ -0,0 +1,186 @@
+use std::io::{Error, ErrorKind, Result};
+
+use crate::comp_structs::bit_io::{BitReader, BitWriter};
+use crate::comp_structs::{
+    chunk_method::{ChunkMethod, DELTA_DICTS},
+    dict_elem::DictElem,
+    dictionary::Dictionary,
+};
+use crate::compress;
+use crate::decompress;
+use crate::utility;
+use crate::utility::VALUES;
+
+/// Forms of a dictionary in a chunk whose dictionaries are stored relative to the previous chunk. Each
+/// dictionary is compared to the one at the same position in the previous chunk.
+const DICT_FULL: u8 = 0;
+const DICT_SAME: u8 = 1;
+const DICT_DELTA: u8 = 2;
+
+/// Moves the entries each dictionary shares with the dictionary at the same position in the previous chunk
+/// to the front, in the order of the previous chunk, so it can be stored as a change of that dictionary.
+///
+/// Dictionaries with more entries than one byte hits can reach are left alone, as their order decides
+/// which hits take two bytes.
+pub fn align(dicts: &mut [Dictionary], previous: &[Dictionary]) {
+    for (dict, prev) in dicts.iter_mut().zip(previous.iter()) {
+        if dict.len() > VALUES {
+            continue;
+        }
+
+        let mut aligned = Dictionary::with_elem_bytes(dict.elem_bytes);
+        aligned.coverage = dict.coverage;
+
+        let shared = prev.elems.iter().filter_map(|elem| dict.get_index(elem.bytes()));
+        let added = (0..dict.len() as u16).filter(|index| prev.get_index(dict.get(*index)).is_none());
+
+        for index in shared.chain(added) {
+            let elem = dict.get_elem(index);
+            aligned.push(DictElem::new(elem.bytes(), elem.occurance));
+        }
+
+        *dict = aligned;
+    }
+}
+
+/// Stores the dictionaries of an encoded chunk relative to those of the previous chunk, when that makes the
+/// chunk smaller, and marks the chunk method.
+///
+/// Each dictionary is then a form byte, followed by the full dictionary, nothing if it is the same as
+/// before, or a bit for each entry of the previous dictionary telling if it is kept, and a dictionary of
+/// the entries added after the kept ones.
+pub fn rewrite_chunk(encoded: Vec<u8>, dicts: &[Dictionary], previous: &[Dictionary]) -> Vec<u8> {
+    if previous.is_empty() || dicts.is_empty() {
+        return encoded;
+    }
+
+    let elem_bytes = dicts[0].elem_bytes;
+
+    // the length and method of the chunk, and the dictionary count of explicitly selected dictionaries
+    let method_at = utility::varint_bytes(&encoded);
+    let method_byte = encoded[method_at];
+    let head = if method_byte == ChunkMethod::PairsSelect as u8 { method_at + 2 } else { method_at + 1 };
+    let full: usize = dicts.iter().map(|dict| utility::dict_size(dict.len(), elem_bytes)).sum();
+
+    let mut relative: Vec<u8> = vec![];
+
+    for (index, dict) in dicts.iter().enumerate() {
+        write_relative(&mut relative, dict, previous.get(index));
+    }
+
+    if relative.len() >= full {
+        return encoded;
+    }
+
+    let rest = &encoded[head + full..];
+    let mut out: Vec<u8> = vec![];
+    utility::write_varint(&mut out, (head - method_at + relative.len() + rest.len()) as u64);
+    out.push(method_byte | DELTA_DICTS);
+    out.extend(&encoded[method_at + 1..head]);
+    out.extend(relative);
+    out.extend(rest);
+
+    out
+}
+
+fn write_relative(buf: &mut Vec<u8>, dict: &Dictionary, prev: Option<&Dictionary>) {
+    let prev = match prev {
+        Some(prev) => prev,
+        None => {
+            buf.push(DICT_FULL);
+            compress::write_dict(buf, dict);
+            return;
+        }
+    };
+
+    if dict.to_vec() == prev.to_vec() {
+        buf.push(DICT_SAME);
+        return;
+    }
+
+    // a delta can only express the kept entries in their old order, followed by the new ones
+    let kept: Vec<bool> = prev.elems.iter().map(|elem| dict.get_index(elem.bytes()).is_some()).collect();
+    let nr_kept = kept.iter().filter(|kept| **kept).count();
+
+    let in_order = prev
+        .elems
+        .iter()
+        .zip(kept.iter())
+        .filter(|(_, kept)| **kept)
+        .zip(dict.elems.iter())
+        .all(|((prev_elem, _), elem)| prev_elem.bytes() == elem.bytes());
+
+    let added = dict.len() - nr_kept;
+    let delta_size = prev.len().div_ceil(8) + utility::dict_size(added, dict.elem_bytes);
+
+    if !in_order || delta_size >= utility::dict_size(dict.len(), dict.elem_bytes) {
+        buf.push(DICT_FULL);
+        compress::write_dict(buf, dict);
+        return;
+    }
+
+    let mut bits = BitWriter::new();
+    for kept in kept {
+        bits.write(kept as u32, 1);
+    }
+
+    let mut added_dict = Dictionary::with_elem_bytes(dict.elem_bytes);
+    for elem in dict.elems[nr_kept..].iter() {
+        added_dict.push(*elem);
+    }
+
+    buf.push(DICT_DELTA);
+    buf.extend(bits.finish());
+    compress::write_dict(buf, &added_dict);
+}
+
+/// Reads `nr_dicts` dictionaries written by `rewrite_chunk`.
+pub fn read_dictionaries(
+    data: &[u8],
+    pos: &mut usize,
+    nr_dicts: usize,
+    elem_bytes: usize,
+    previous: &[Dictionary],
+) -> Result<Vec<Dictionary>> {
+    let mut dicts: Vec<Dictionary> = Vec::with_capacity(nr_dicts);
+
+    for index in 0..nr_dicts {
+        let form = decompress::read_bytes(data, pos, 1)?[0];
+
+        if form == DICT_FULL {
+            dicts.push(decompress::get_dictionary(data, pos, elem_bytes)?);
+            continue;
+        }
+
+        let prev = match previous.get(index) {
+            Some(prev) => prev,
+            None => return Err(Error::new(ErrorKind::InvalidData, "no dictionary in the previous chunk")),
+        };
+
+        let dict = match form {
+            DICT_SAME => prev.clone(),
+            DICT_DELTA => {
+                let bitmap = decompress::read_bytes(data, pos, prev.len().div_ceil(8))?;
+                let mut bits = BitReader::new(bitmap);
+                let mut dict = Dictionary::with_elem_bytes(elem_bytes);
+
+                for elem in prev.elems.iter() {
+                    if bits.read(1)? == 1 {
+                        dict.push(DictElem::new(elem.bytes(), 0));
+                    }
+                }
+
+                for elem in decompress::get_dictionary(data, pos, elem_bytes)?.elems {
+                    dict.push(elem);
+                }
+
+                dict
+            }
+            _ => return Err(Error::new(ErrorKind::InvalidData, "unknown dictionary form")),
+        };
+
+        dicts.push(dict);
+    }
+
+    Ok(dicts)
+}
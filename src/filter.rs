@@ -0,0 +1,198 @@
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+
+/// Opcodes of the x86 relative call and jump, followed by a 32 bit little endian displacement.
+const X86_CALL: u8 = 0xE8;
+const X86_JUMP: u8 = 0xE9;
+
+/// Bits of the addresses the branch filter converts, as a signed number. Displacements outside of this
+/// range are left alone, they are rarely real branches.
+const BRANCH_BITS: u32 = 25;
+
+/// A reversible transform applied to a file before the first layer, making it easier to find pairs in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// Replaces each byte by its difference to the byte `stride` bytes before it.
+    Delta { stride: u8 },
+    /// Splits records of `width` bytes into planes holding the same byte of every record. A partial record
+    /// at the end is kept as it is.
+    Transpose { width: u8 },
+    /// Turns the relative addresses of x86 calls and jumps into absolute ones, which repeat when the same
+    /// function is called from different places.
+    Branch,
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::Delta { stride } => write!(f, "delta:{}", stride),
+            Filter::Transpose { width } => write!(f, "transpose:{}", width),
+            Filter::Branch => write!(f, "bcj"),
+        }
+    }
+}
+
+impl Filter {
+    /// Parses a filter written as `delta:STRIDE`, `transpose:WIDTH` or `bcj`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let (name, param) = match text.split_once(':') {
+            Some((name, param)) => (name, Some(param)),
+            None => (text, None),
+        };
+
+        let param = param.map(|param| param.parse::<u8>());
+
+        let filter = match (name, param) {
+            ("delta", Some(Ok(stride))) => Filter::Delta { stride },
+            ("transpose", Some(Ok(width))) => Filter::Transpose { width },
+            ("bcj", None) => Filter::Branch,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown filter {}", text))),
+        };
+
+        filter.check(ErrorKind::InvalidInput)?;
+        Ok(filter)
+    }
+
+    /// Writes the filter in 2 bytes, its kind and parameter.
+    pub fn to_bytes(self) -> [u8; 2] {
+        match self {
+            Filter::Delta { stride } => [0, stride],
+            Filter::Transpose { width } => [1, width],
+            Filter::Branch => [2, 0],
+        }
+    }
+
+    pub fn from_bytes(bytes: [u8; 2]) -> Result<Self> {
+        let filter = match bytes {
+            [0, stride] => Filter::Delta { stride },
+            [1, width] => Filter::Transpose { width },
+            [2, 0] => Filter::Branch,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "unknown filter")),
+        };
+
+        filter.check(ErrorKind::InvalidData)?;
+        Ok(filter)
+    }
+
+    fn check(self, kind: ErrorKind) -> Result<()> {
+        match self {
+            Filter::Delta { stride: 0 } => Err(Error::new(kind, "delta stride must be at least 1")),
+            Filter::Transpose { width: 0..=1 } => Err(Error::new(kind, "transpose width must be at least 2")),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn apply(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Filter::Delta { stride } => delta_encode(data, stride as usize),
+            Filter::Transpose { width } => transpose(data, width as usize),
+            Filter::Branch => branch_convert(data, true),
+        }
+    }
+
+    pub fn revert(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Filter::Delta { stride } => delta_decode(data, stride as usize),
+            Filter::Transpose { width } => untranspose(data, width as usize),
+            Filter::Branch => branch_convert(data, false),
+        }
+    }
+}
+
+/// Applies the filters in order.
+pub fn apply_all(filters: &[Filter], data: &[u8]) -> Vec<u8> {
+    filters.iter().fold(data.to_vec(), |data, filter| filter.apply(&data))
+}
+
+/// Reverts the filters, the last one first.
+pub fn revert_all(filters: &[Filter], data: &[u8]) -> Vec<u8> {
+    filters.iter().rev().fold(data.to_vec(), |data, filter| filter.revert(&data))
+}
+
+fn delta_encode(data: &[u8], stride: usize) -> Vec<u8> {
+    let mut out = data.to_vec();
+
+    for i in stride..data.len() {
+        out[i] = data[i].wrapping_sub(data[i - stride]);
+    }
+
+    out
+}
+
+fn delta_decode(data: &[u8], stride: usize) -> Vec<u8> {
+    let mut out = data.to_vec();
+
+    for i in stride..out.len() {
+        out[i] = out[i].wrapping_add(out[i - stride]);
+    }
+
+    out
+}
+
+fn transpose(data: &[u8], width: usize) -> Vec<u8> {
+    let records = data.len() / width;
+    let mut out = Vec::with_capacity(data.len());
+
+    for plane in 0..width {
+        out.extend(data.iter().skip(plane).step_by(width).take(records));
+    }
+
+    out.extend(&data[records * width..]);
+    out
+}
+
+fn untranspose(data: &[u8], width: usize) -> Vec<u8> {
+    let records = data.len() / width;
+    let mut out = vec![0u8; data.len()];
+
+    for (plane, bytes) in data[..records * width].chunks_exact(records.max(1)).enumerate() {
+        for (record, byte) in bytes.iter().enumerate() {
+            out[record * width + plane] = *byte;
+        }
+    }
+
+    out[records * width..].copy_from_slice(&data[records * width..]);
+    out
+}
+
+/// Converts the displacement of each call and jump to an absolute address, or back.
+///
+/// Only displacements whose top byte is all zeros or all ones are converted, and the result is kept within
+/// the same signed range, so the decoder sees the same top bytes and finds the same branches. The bytes of a
+/// converted branch are skipped, other bytes are searched for the next opcode.
+///
+/// An opcode that was not converted looks ahead at a top byte which a branch starting in its next 3 bytes
+/// would change, so like the prev mask of the xz filter, no branch is converted within 3 bytes after an opcode.
+fn branch_convert(data: &[u8], encode: bool) -> Vec<u8> {
+    let mut out = data.to_vec();
+    let shift = 32 - BRANCH_BITS;
+    let mut i = 0;
+    // end of the last converted branch, the bytes from here on are the same on both sides
+    let mut converted_end = 0;
+
+    while i + 5 <= out.len() {
+        let opcode = out[i];
+        let top = out[i + 4];
+        let after_opcode = out[converted_end.max(i.saturating_sub(3))..i]
+            .iter()
+            .any(|byte| *byte == X86_CALL || *byte == X86_JUMP);
+
+        if (opcode != X86_CALL && opcode != X86_JUMP) || (top != 0x00 && top != 0xFF) || after_opcode {
+            i += 1;
+            continue;
+        }
+
+        let value = u32::from_le_bytes([out[i + 1], out[i + 2], out[i + 3], out[i + 4]]);
+        let next = (i + 5) as u32;
+        let converted = if encode { value.wrapping_add(next) } else { value.wrapping_sub(next) };
+
+        // sign extend from the converted bits
+        let converted = ((converted << shift) as i32 >> shift) as u32;
+        out[i + 1..i + 5].copy_from_slice(&converted.to_le_bytes());
+
+        i += 5;
+        converted_end = i;
+    }
+
+    out
+}
@@ -0,0 +1,236 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::prelude::*;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::comp_structs::bit_io::{BitReader, BitWriter};
+use crate::utility;
+use crate::utility::{CHUNK_MAX_SIZE, DEBUG};
+
+/// Longest code allowed, so every code length fits in half a byte of the table.
+const MAX_CODE_BITS: u8 = 15;
+
+const SYMBOLS: usize = 256;
+
+/// Block stored as is, when coding would not make it smaller.
+const BLOCK_STORED: u8 = 0;
+const BLOCK_HUFFMAN: u8 = 1;
+
+/// Codes `data` in blocks of up to `CHUNK_MAX_SIZE` bytes, each with its own canonical code table.
+///
+/// A block is its total length in 4 bytes and a method byte. Coded blocks follow with the code lengths of
+/// all 256 bytes as half bytes, the decoded length in 4 bytes and the codes.
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = vec![];
+
+    for block in data.chunks(CHUNK_MAX_SIZE as usize) {
+        let coded = encode_block(block);
+
+        let (method, payload) = if coded.len() < block.len() {
+            (BLOCK_HUFFMAN, coded.as_slice())
+        } else {
+            (BLOCK_STORED, block)
+        };
+
+        out.extend(utility::val_to_u8_vec(4 + 1 + payload.len(), 4));
+        out.push(method);
+        out.extend(payload);
+    }
+
+    if DEBUG {
+        println!("\nHuffman stage: {} Bytes -> {} Bytes", data.len(), out.len());
+    }
+
+    out
+}
+
+fn encode_block(block: &[u8]) -> Vec<u8> {
+    let mut freqs = [0u64; SYMBOLS];
+
+    for byte in block {
+        freqs[*byte as usize] += 1;
+    }
+
+    let lengths = code_lengths(&freqs);
+    let codes = canonical_codes(&lengths);
+
+    let mut out: Vec<u8> = lengths.chunks(2).map(|pair| (pair[0] << 4) | pair[1]).collect();
+    out.extend(utility::val_to_u8_vec(block.len(), 4));
+
+    let mut writer = BitWriter::new();
+
+    for byte in block {
+        writer.write(codes[*byte as usize], lengths[*byte as usize] as u32);
+    }
+
+    out.extend(writer.finish());
+    out
+}
+
+/// Finds the code length of each byte, halving the counts until no code is longer than `MAX_CODE_BITS`.
+fn code_lengths(freqs: &[u64; SYMBOLS]) -> [u8; SYMBOLS] {
+    let mut freqs = *freqs;
+
+    loop {
+        let lengths = tree_lengths(&freqs);
+
+        if lengths.iter().all(|len| *len <= MAX_CODE_BITS) {
+            return lengths;
+        }
+
+        for freq in freqs.iter_mut().filter(|freq| **freq > 0) {
+            *freq = (*freq / 2).max(1);
+        }
+    }
+}
+
+fn tree_lengths(freqs: &[u64; SYMBOLS]) -> [u8; SYMBOLS] {
+    let mut lengths = [0u8; SYMBOLS];
+
+    // nodes below SYMBOLS are leaves, the rest are merged pairs
+    let mut parents: Vec<usize> = vec![0; SYMBOLS];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = BinaryHeap::new();
+
+    for (symbol, freq) in freqs.iter().enumerate() {
+        if *freq > 0 {
+            heap.push(Reverse((*freq, symbol)));
+        }
+    }
+
+    // a single byte still needs a one bit code
+    if heap.len() == 1 {
+        let Reverse((_, symbol)) = heap.pop().unwrap();
+        lengths[symbol] = 1;
+        return lengths;
+    }
+
+    while heap.len() > 1 {
+        let Reverse((freq_a, a)) = heap.pop().unwrap();
+        let Reverse((freq_b, b)) = heap.pop().unwrap();
+        let node = parents.len();
+
+        parents.push(node);
+        parents[a] = node;
+        parents[b] = node;
+        heap.push(Reverse((freq_a + freq_b, node)));
+    }
+
+    for symbol in 0..SYMBOLS {
+        if freqs[symbol] == 0 {
+            continue;
+        }
+
+        let mut node = symbol;
+
+        while parents[node] != node {
+            node = parents[node];
+            lengths[symbol] += 1;
+        }
+    }
+
+    lengths
+}
+
+/// Assigns codes in order of length, and by byte within the same length.
+fn canonical_codes(lengths: &[u8; SYMBOLS]) -> [u32; SYMBOLS] {
+    let mut codes = [0u32; SYMBOLS];
+    let mut code = 0u32;
+
+    for len in 1..=MAX_CODE_BITS {
+        for symbol in 0..SYMBOLS {
+            if lengths[symbol] == len {
+                codes[symbol] = code;
+                code += 1;
+            }
+        }
+
+        code <<= 1;
+    }
+
+    codes
+}
+
+pub fn decode<W: Write>(data: &[u8], writer: &mut W) -> Result<()> {
+    let mut pos = 0;
+
+    while pos < data.len() {
+        if data.len() - pos < 5 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "huffman block ended unexpectedly"));
+        }
+
+        let block_len = utility::u8_vec_to_u64(&data[pos..pos + 4]) as usize;
+
+        if block_len < 5 || data.len() - pos < block_len {
+            return Err(Error::new(ErrorKind::InvalidData, "huffman block length out of range"));
+        }
+
+        let payload = &data[pos + 5..pos + block_len];
+
+        match data[pos + 4] {
+            BLOCK_STORED => writer.write_all(payload)?,
+            BLOCK_HUFFMAN => writer.write_all(&decode_block(payload)?)?,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "unknown huffman block method")),
+        }
+
+        pos += block_len;
+    }
+
+    Ok(())
+}
+
+fn decode_block(payload: &[u8]) -> Result<Vec<u8>> {
+    let table_bytes = SYMBOLS / 2;
+
+    if payload.len() < table_bytes + 4 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "huffman block ended unexpectedly"));
+    }
+
+    let mut lengths = [0u8; SYMBOLS];
+
+    for (i, byte) in payload[..table_bytes].iter().enumerate() {
+        lengths[2 * i] = byte >> 4;
+        lengths[2 * i + 1] = byte & 0x0F;
+    }
+
+    // the number of codes of each length, and the bytes sorted the way codes were assigned
+    let mut counts = [0u32; MAX_CODE_BITS as usize + 1];
+    let mut sorted: Vec<u8> = vec![];
+
+    for len in 1..=MAX_CODE_BITS {
+        for (symbol, _) in lengths.iter().enumerate().filter(|(_, l)| **l == len) {
+            counts[len as usize] += 1;
+            sorted.push(symbol as u8);
+        }
+    }
+
+    let decoded_len = utility::u8_vec_to_u64(&payload[table_bytes..table_bytes + 4]) as usize;
+    let mut reader = BitReader::new(&payload[table_bytes + 4..]);
+    let mut out: Vec<u8> = Vec::with_capacity(decoded_len.min(CHUNK_MAX_SIZE as usize));
+
+    for _ in 0..decoded_len {
+        let mut code = 0u32;
+        let mut first = 0u32;
+        let mut offset = 0u32;
+        let mut symbol = None;
+
+        for count in counts.iter().skip(1) {
+            code |= reader.read(1)?;
+
+            if code < first + count {
+                symbol = Some(sorted[(offset + code - first) as usize]);
+                break;
+            }
+
+            offset += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        match symbol {
+            Some(symbol) => out.push(symbol),
+            None => return Err(Error::new(ErrorKind::InvalidData, "invalid huffman code")),
+        }
+    }
+
+    Ok(out)
+}
@@ -0,0 +1,53 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, Result};
+use std::ops::Deref;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+/// Input data for a layer, either memory mapped or read into memory.
+pub enum Input {
+    Mapped(Mmap),
+    Buffered(Vec<u8>),
+}
+
+impl Input {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let meta = file.metadata()?;
+
+        // only regular, non-empty files can be mapped
+        if meta.is_file() && meta.len() > 0 {
+            // safety: the mapping is only read, and the files handled are not modified while mapped
+            if let Ok(map) = unsafe { Mmap::map(&file) } {
+                return Ok(Input::Mapped(map));
+            }
+        }
+
+        Input::buffered(file)
+    }
+
+    /// Reads the whole source into memory, used for pipes and other sources that can not be mapped.
+    pub fn buffered<R: Read>(source: R) -> Result<Self> {
+        let mut buf = vec![];
+        BufReader::new(source).read_to_end(&mut buf)?;
+
+        Ok(Input::Buffered(buf))
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, Input::Mapped(_))
+    }
+}
+
+impl Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Mapped(map) => map,
+            Input::Buffered(buf) => buf,
+        }
+    }
+}
@@ -0,0 +1,19 @@
+pub mod bwt;
+pub mod chunker;
+pub mod comp_structs;
+pub mod compress;
+pub mod decompress;
+pub mod dict_delta;
+pub mod filter;
+pub mod huffman;
+pub mod input;
+pub mod matcher;
+pub mod optimal_parse;
+pub mod prediction;
+pub mod preset;
+pub mod rans;
+pub mod regions;
+pub mod repair;
+pub mod selection;
+pub mod strategy;
+pub mod utility;
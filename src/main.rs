@@ -3,27 +3,47 @@ use std::path::PathBuf;
 use std::time::Instant;
 
 extern crate clap;
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
 
-pub mod comp_structs;
-mod compress;
-mod decompress;
-mod utility;
+use pcomp::comp_structs::options::{Mode, Options};
+use pcomp::filter::Filter;
+use pcomp::preset::{self, Preset};
+use pcomp::utility::{DEFAULT_LEVEL, MAX_ELEM_BYTES, MAX_LEVEL, MIN_ELEM_BYTES, MIN_LEVEL};
+use pcomp::{compress, decompress};
 
 #[derive(PartialEq)]
 enum Action {
     None,
     Compress,
     Decompress,
+    Train { samples: Vec<PathBuf>, output: PathBuf },
 }
 
 fn main() -> Result<()> {
-    let (path, action) = argument_handler()?;
+    let (path, action, options) = argument_handler()?;
     let time = Instant::now();
 
+    let file_name = path.file_name().unwrap_or_default().to_str().unwrap();
+
     let result_path = match action {
-        Action::Compress => compress::run(&path)?,
-        Action::Decompress => decompress::run(&path)?,
+        Action::Compress => {
+            println!("\nCompressing file: {}", file_name);
+            compress::run(&path, &options)?
+        }
+        Action::Decompress => {
+            println!("Decompressing file {}", file_name);
+            decompress::run_with_preset(&path, options.preset.as_ref())?
+        }
+        Action::Train { ref samples, ref output } => {
+            println!("\nTraining preset on {} samples", samples.len());
+
+            let data = samples.iter().map(std::fs::read).collect::<Result<Vec<_>>>()?;
+            let preset = preset::train(&data, options.elem_bytes)?;
+            preset.save(output)?;
+
+            println!("{} layers, id {:08x}", preset.layers.len(), preset.id());
+            output.clone()
+        }
         Action::None => PathBuf::from(""),
     };
 
@@ -37,11 +57,28 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn argument_handler() -> Result<(PathBuf, Action)> {
+/// Flags of the compression levels and their help, from the fastest to the smallest.
+const LEVELS: [(&str, &str); 9] = [
+    ("1", "Level 1, fastest: a single layer of pairs in small chunks"),
+    ("2", "Level 2: two layers of pairs in smaller chunks"),
+    ("3", "Level 3: layers of pairs until they stop paying off"),
+    ("4", "Level 4, default: also matches and runs"),
+    ("5", "Level 5: also codes the last layer with Huffman codes"),
+    ("6", "Level 6: also tries rANS on the top layer"),
+    ("7", "Level 7: also picks dictionary entries by the bytes they save"),
+    ("8", "Level 8: also parses the top layer for the fewest bytes"),
+    ("9", "Level 9, smallest: also builds exact dictionaries, and encodes layers until one saves nothing"),
+];
+
+fn argument_handler() -> Result<(PathBuf, Action, Options)> {
+    let levels: Vec<Arg> =
+        LEVELS.iter().map(|(level, help)| Arg::with_name(level).short(level).requires("compress").help(help)).collect();
+
     let matches = App::new("Pyramid Compression")
         .version("0.1.0")
         .author("Tom Axblad <tom.axblad@gmail.com>")
         .about("A parallel compression algorithm")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("compress")
                 .short("c")
@@ -62,6 +99,157 @@ fn argument_handler() -> Result<(PathBuf, Action)> {
                 .help("Decompresses file")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("width")
+                .short("w")
+                .long("width")
+                .requires("compress")
+                .value_name("BYTES")
+                .help("Bytes in each dictionary element, 2 to 4")
+                .takes_value(true)
+                .validator(validate_width),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .short("m")
+                .long("mode")
+                .requires("compress")
+                .value_name("MODE")
+                .help("Encodes layers with pair dictionaries (default), or a Re-Pair grammar per chunk")
+                .takes_value(true)
+                .possible_values(&["pairs", "repair"]),
+        )
+        .args(&levels)
+        .group(ArgGroup::with_name("level").args(&LEVELS.map(|(level, _)| level)))
+        .arg(
+            Arg::with_name("huffman")
+                .long("huffman")
+                .requires("compress")
+                .help("Codes the final layer with Huffman codes"),
+        )
+        .arg(
+            Arg::with_name("rans")
+                .long("rans")
+                .requires("compress")
+                .help("Codes the tokens of each chunk with rANS, slower but smaller"),
+        )
+        .arg(
+            Arg::with_name("cost-select")
+                .long("cost-select")
+                .requires("compress")
+                .help("Picks dictionary entries by the bytes they save, slower but smaller"),
+        )
+        .arg(
+            Arg::with_name("optimal")
+                .long("optimal")
+                .requires("compress")
+                .help("Parses each chunk for the fewest bytes, slower but smaller"),
+        )
+        .arg(
+            Arg::with_name("regions")
+                .long("regions")
+                .requires("compress")
+                .help("Tries dictionaries for regions of each chunk, slower but smaller"),
+        )
+        .arg(
+            Arg::with_name("no-matches")
+                .long("no-matches")
+                .requires("compress")
+                .help("Codes repeated blocks with pairs only, without match tokens"),
+        )
+        .arg(
+            Arg::with_name("no-runs")
+                .long("no-runs")
+                .requires("compress")
+                .help("Codes runs of repeated bytes and elements without run tokens or run-length encoded chunks"),
+        )
+        .arg(
+            Arg::with_name("entropy-chunks")
+                .long("entropy-chunks")
+                .requires("compress")
+                .help("Also tries coding whole chunks as plain bytes with rANS"),
+        )
+        .arg(
+            Arg::with_name("exact-dicts")
+                .long("exact-dicts")
+                .requires("compress")
+                .help("Builds the dictionaries from the element counts of the whole chunk"),
+        )
+        .arg(
+            Arg::with_name("bwt")
+                .long("bwt")
+                .requires("compress")
+                .help("Also tries each chunk of the input after the Burrows-Wheeler transform"),
+        )
+        .arg(
+            Arg::with_name("no-literals")
+                .long("no-literals")
+                .requires("compress")
+                .help("Keeps missed bytes between the tokens instead of coding them apart"),
+        )
+        .arg(
+            Arg::with_name("max-layers")
+                .long("max-layers")
+                .requires("compress")
+                .value_name("N")
+                .help("Writes at most N layers, 0 to 255")
+                .takes_value(true)
+                .validator(validate_max_layers),
+        )
+        .arg(
+            Arg::with_name("min-gain")
+                .long("min-gain")
+                .requires("compress")
+                .value_name("PERCENT")
+                .help("Stops adding layers once one saves less than this part of its input, 0.1 by default")
+                .takes_value(true)
+                .validator(validate_min_gain),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .requires("compress")
+                .value_name("FILTERS")
+                .help("Filters applied in order before compressing: delta:STRIDE, transpose:WIDTH or bcj, separated by commas")
+                .takes_value(true)
+                .validator(validate_filters),
+        )
+        .arg(
+            Arg::with_name("preset")
+                .long("preset")
+                .value_name("FILE")
+                .help("Preset dictionaries from `train`, needed again to decompress")
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("train")
+                .about("Trains preset dictionaries on sample files")
+                .arg(
+                    Arg::with_name("samples")
+                        .value_name("SAMPLES")
+                        .help("Files like the ones the preset will be used for")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Where to write the preset")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("width")
+                        .short("w")
+                        .long("width")
+                        .value_name("BYTES")
+                        .help("Bytes in each dictionary element, 2 to 4")
+                        .takes_value(true)
+                        .validator(validate_width),
+                ),
+        )
         .get_matches();
 
     let mut action = Action::None;
@@ -75,5 +263,85 @@ fn argument_handler() -> Result<(PathBuf, Action)> {
         action = Action::Decompress;
     }
 
-    Ok((PathBuf::from(path_str), action))
+    let level = (MIN_LEVEL..=MAX_LEVEL).find(|level| matches.is_present(level.to_string())).unwrap_or(DEFAULT_LEVEL);
+    let mut options = Options::with_level(level)?;
+
+    if let Some(train) = matches.subcommand_matches("train") {
+        if let Some(width) = train.value_of("width") {
+            options.elem_bytes = width.parse().unwrap();
+        }
+
+        let samples = train.values_of("samples").unwrap().map(PathBuf::from).collect();
+        let output = PathBuf::from(train.value_of("output").unwrap());
+
+        return Ok((PathBuf::from(""), Action::Train { samples, output }, options));
+    }
+
+    // compressing with a preset uses its element width, unless another one is asked for
+    if let Some(preset_path) = matches.value_of("preset") {
+        let preset = Preset::load(&PathBuf::from(preset_path))?;
+        options.elem_bytes = preset.elem_bytes;
+        options.preset = Some(preset);
+    }
+
+    if let Some(width) = matches.value_of("width") {
+        options.elem_bytes = width.parse().unwrap();
+    }
+
+    if matches.value_of("mode") == Some("repair") {
+        options.mode = Mode::RePair;
+    }
+
+    // the flags add to the options of the level
+    options.huffman |= matches.is_present("huffman");
+    options.rans |= matches.is_present("rans");
+    options.cost_select |= matches.is_present("cost-select");
+    options.optimal_parse |= matches.is_present("optimal");
+    options.regions |= matches.is_present("regions");
+    options.matches &= !matches.is_present("no-matches");
+    options.runs &= !matches.is_present("no-runs");
+    options.literals &= !matches.is_present("no-literals");
+    options.entropy_chunks |= matches.is_present("entropy-chunks");
+    options.exact_dicts |= matches.is_present("exact-dicts");
+    options.bwt |= matches.is_present("bwt");
+
+    if let Some(max_layers) = matches.value_of("max-layers") {
+        options.max_layers = max_layers.parse().unwrap();
+    }
+
+    if let Some(min_gain) = matches.value_of("min-gain") {
+        options.min_gain = min_gain.parse::<f64>().unwrap() / 100.0;
+    }
+
+    if let Some(filters) = matches.value_of("filter") {
+        options.filters = parse_filters(filters)?;
+    }
+
+    Ok((PathBuf::from(path_str), action, options))
+}
+
+fn validate_width(width: String) -> std::result::Result<(), String> {
+    match width.parse::<usize>() {
+        Ok(w) if (MIN_ELEM_BYTES..=MAX_ELEM_BYTES).contains(&w) => Ok(()),
+        _ => Err(format!("width must be between {} and {}", MIN_ELEM_BYTES, MAX_ELEM_BYTES)),
+    }
+}
+
+fn validate_max_layers(max_layers: String) -> std::result::Result<(), String> {
+    max_layers.parse::<u8>().map(|_| ()).map_err(|_| String::from("max layers must be between 0 and 255"))
+}
+
+fn validate_min_gain(min_gain: String) -> std::result::Result<(), String> {
+    match min_gain.parse::<f64>() {
+        Ok(gain) if (0.0..100.0).contains(&gain) => Ok(()),
+        _ => Err(String::from("min gain must be a percentage from 0 up to 100")),
+    }
+}
+
+fn parse_filters(filters: &str) -> Result<Vec<Filter>> {
+    filters.split(',').map(Filter::parse).collect()
+}
+
+fn validate_filters(filters: String) -> std::result::Result<(), String> {
+    parse_filters(&filters).map(|_| ()).map_err(|err| err.to_string())
 }
@@ -0,0 +1,81 @@
+/// Bytes hashed to find earlier occurrences.
+const HASH_BYTES: usize = 4;
+const HASH_BITS: u32 = 16;
+
+/// Earlier occurrences compared before settling for the longest match found so far.
+const MAX_CHAIN: usize = 32;
+
+/// No earlier position with this hash.
+const NONE: u32 = u32::MAX;
+
+/// Finds the longest earlier repeat of the bytes at a position, through hash chains linking each position
+/// of the data to the previous position whose first bytes have the same hash.
+pub struct Matcher<'a> {
+    data: &'a [u8],
+    head: Vec<u32>,
+    prev: Vec<u32>,
+    inserted: usize,
+}
+
+impl<'a> Matcher<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Matcher { data, head: vec![NONE; 1 << HASH_BITS], prev: vec![NONE; data.len()], inserted: 0 }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = &self.data[pos..pos + HASH_BYTES];
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Links every position before `pos` into the chains.
+    fn insert_until(&mut self, pos: usize) {
+        let end = pos.min((self.data.len() + 1).saturating_sub(HASH_BYTES));
+
+        while self.inserted < end {
+            let hash = self.hash(self.inserted);
+            self.prev[self.inserted] = self.head[hash];
+            self.head[hash] = self.inserted as u32;
+            self.inserted += 1;
+        }
+    }
+
+    /// The distance back to the longest repeat of the bytes at `pos`, and its length, up to `max_len`. The
+    /// repeat may run into the bytes at `pos` itself.
+    pub fn longest(&mut self, pos: usize, max_len: usize) -> Option<(usize, usize)> {
+        self.insert_until(pos);
+
+        if pos + HASH_BYTES > self.data.len() {
+            return None;
+        }
+
+        let max_len = max_len.min(self.data.len() - pos);
+        let mut candidate = self.head[self.hash(pos)];
+        let mut best: Option<(usize, usize)> = None;
+
+        for _ in 0..MAX_CHAIN {
+            if candidate == NONE {
+                break;
+            }
+
+            let start = candidate as usize;
+            let len = self.data[start..]
+                .iter()
+                .zip(self.data[pos..pos + max_len].iter())
+                .take_while(|(earlier, current)| earlier == current)
+                .count();
+
+            if best.is_none_or(|(_, best_len)| len > best_len) {
+                best = Some((pos - start, len));
+
+                if len == max_len {
+                    break;
+                }
+            }
+
+            candidate = self.prev[start];
+        }
+
+        best.filter(|(_, len)| *len >= HASH_BYTES)
+    }
+}
@@ -0,0 +1,160 @@
+use crate::comp_structs::{dict_elem::DictElem, dictionary::Dictionary, token::Token};
+use crate::utility::VALUES;
+
+const UNREACHED: u32 = u32::MAX;
+
+/// How a state was reached, and whether the state before it was inside a miss.
+const FROM_HIT: u8 = 0;
+const FROM_MISS: u8 = 1;
+const PREV_IN_MISS: u8 = 2;
+
+/// Finds the cheapest sequence of hits and misses for a chunk, in the same tokens as the greedy parse.
+///
+/// The parse runs over every byte position, with a state for each dictionary the decoder can be using and
+/// whether the last token was a miss. A hit costs its index bytes and keeps the dictionary. A missed byte
+/// costs itself, a header if it starts a new miss, and moves the decoder on to the next dictionary. Single
+/// hits are allowed whenever they are cheaper, and miss headers are counted as one byte even for runs above
+/// 63 bytes.
+pub fn parse(dicts: &mut [Dictionary], chunk: &[u8]) -> (Vec<Token>, u64, u64) {
+    let reversed = cheapest_path(dicts, chunk);
+
+    // an element hit only once saves less than it takes in the dictionary, so parse again without them
+    let mut uses: Vec<Vec<u32>> = dicts.iter().map(|dict| vec![0; dict.len()]).collect();
+    for token in reversed.iter() {
+        if let Token::Hit { dict, index } = token {
+            uses[*dict as usize][*index as usize] += 1;
+        }
+    }
+
+    let reversed = if uses.iter().flatten().any(|count| *count == 1) {
+        for (dict, dict_uses) in dicts.iter_mut().zip(uses.iter()) {
+            let mut kept = Dictionary::with_elem_bytes(dict.elem_bytes);
+            kept.coverage = dict.coverage;
+
+            for (elem, count) in dict.elems.iter().zip(dict_uses.iter()) {
+                if *count > 1 {
+                    kept.push(DictElem::new(elem.bytes(), elem.occurance));
+                }
+            }
+
+            *dict = kept;
+        }
+
+        cheapest_path(dicts, chunk)
+    } else {
+        reversed
+    };
+
+    let elem_bytes = dicts[0].elem_bytes;
+    let mut tokens: Vec<Token> = vec![];
+    let mut hits = 0u64;
+    let mut misses = 0u64;
+
+    // join the missed bytes into runs, and count the useage of the dictionary elements
+    for token in reversed.into_iter().rev() {
+        match token {
+            Token::Hit { dict, index } => {
+                dicts[dict as usize].increment_usage(index);
+                hits += elem_bytes as u64;
+                tokens.push(token);
+            }
+            Token::Miss(range) => {
+                misses += range.len() as u64;
+
+                if let Some(Token::Miss(last)) = tokens.last_mut() {
+                    last.end = range.end;
+                } else {
+                    tokens.push(Token::Miss(range));
+                }
+            }
+            Token::Match { .. } | Token::ByteRun { .. } | Token::HitRun(_) => {
+                unreachable!("the parse does not look for matches or runs")
+            }
+        }
+    }
+
+    (tokens, hits, misses)
+}
+
+/// Runs the parse over the chunk, returning the tokens of the cheapest path from the end of the chunk back.
+fn cheapest_path(dicts: &[Dictionary], chunk: &[u8]) -> Vec<Token> {
+    let elem_bytes = dicts[0].elem_bytes;
+    let nr_dicts = dicts.len();
+    let states = nr_dicts * 2;
+    let len = chunk.len();
+
+    // cost and origin of each state, in position order
+    let mut cost = vec![UNREACHED; (len + 1) * states];
+    let mut from = vec![0u8; (len + 1) * states];
+    cost[0] = 0;
+
+    for pos in 0..len {
+        for (dict_index, dict) in dicts.iter().enumerate() {
+            for in_miss in 0..2 {
+                let state = pos * states + dict_index * 2 + in_miss;
+                let current = cost[state];
+
+                if current == UNREACHED {
+                    continue;
+                }
+
+                let prev_flag = if in_miss == 1 { PREV_IN_MISS } else { 0 };
+
+                if pos + elem_bytes <= len {
+                    if let Some(index) = dict.get_index(&chunk[pos..pos + elem_bytes]) {
+                        let next = (pos + elem_bytes) * states + dict_index * 2;
+                        let next_cost = current + hit_bytes(index);
+
+                        if next_cost < cost[next] {
+                            cost[next] = next_cost;
+                            from[next] = FROM_HIT | prev_flag;
+                        }
+                    }
+                }
+
+                let next = (pos + 1) * states + ((dict_index + 1) % nr_dicts) * 2 + 1;
+                let next_cost = current + 1 + (1 - in_miss as u32);
+
+                if next_cost < cost[next] {
+                    cost[next] = next_cost;
+                    from[next] = FROM_MISS | prev_flag;
+                }
+            }
+        }
+    }
+
+    // the cheapest state at the end of the chunk, preferring the lowest state on ties
+    let end = len * states;
+    let mut state = (0..states).min_by_key(|state| cost[end + state]).unwrap();
+    let mut pos = len;
+
+    // walk back through the chosen states, collecting the tokens in reverse
+    let mut reversed: Vec<Token> = vec![];
+
+    while pos > 0 {
+        let dict_index = state / 2;
+        let origin = from[pos * states + state];
+        let prev_in_miss = if origin & PREV_IN_MISS != 0 { 1 } else { 0 };
+
+        if origin & FROM_MISS != 0 {
+            pos -= 1;
+            reversed.push(Token::Miss(pos..pos + 1));
+            state = ((dict_index + nr_dicts - 1) % nr_dicts) * 2 + prev_in_miss;
+        } else {
+            pos -= elem_bytes;
+            let index = dicts[dict_index].get_index(&chunk[pos..pos + elem_bytes]).unwrap();
+            reversed.push(Token::Hit { dict: dict_index as u8, index });
+            state = dict_index * 2 + prev_in_miss;
+        }
+    }
+
+    reversed
+}
+
+fn hit_bytes(index: u16) -> u32 {
+    if (index as usize) < VALUES {
+        1
+    } else {
+        2
+    }
+}
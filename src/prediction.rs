@@ -0,0 +1,122 @@
+use crate::comp_structs::{dict_elem::DictElem, dictionary::Dictionary, elem_counter::ElemCounter};
+use crate::compress;
+use crate::matcher::Matcher;
+use crate::utility::{self, MAX_MATCH, MAX_VALUES, MIN_OCCATIONS, VALUES};
+
+/// Bytes in each sampled block, about what the chunks of a layer hold.
+const SAMPLE_BYTES: usize = 1 << 18;
+
+/// Most blocks sampled from a layer.
+const SAMPLES: usize = 8;
+
+/// Estimates how many bytes a pair layer would save on `data`, without encoding it.
+///
+/// Evenly spread blocks of the data are parsed greedily with the most common elements of each alignment in
+/// the block, with and without extended dictionaries, and with `matches` also taking earlier repeats like
+/// the layer would. Hits save the element less the hit, matches the bytes they repeat less the token, each
+/// run of misses pays for a header and the dictionaries are paid for. The savings of the blocks are scaled up to
+/// the whole layer. Chunks cut where the data changes and dictionaries stored relative to the previous
+/// chunk are not modelled, so the estimate is rough.
+pub fn layer_gain(data: &[u8], elem_bytes: usize, matches: bool) -> i64 {
+    let blocks = data.len().div_ceil(SAMPLE_BYTES);
+    let step = blocks.div_ceil(SAMPLES).max(1);
+
+    let mut sampled = 0usize;
+    let mut gain = 0i64;
+
+    for block in data.chunks(SAMPLE_BYTES).step_by(step) {
+        sampled += block.len();
+        gain += block_gain(block, elem_bytes, matches);
+    }
+
+    if sampled == 0 {
+        return 0;
+    }
+
+    gain * data.len() as i64 / sampled as i64
+}
+
+fn block_gain(block: &[u8], elem_bytes: usize, matches: bool) -> i64 {
+    let ranked: Vec<Vec<(usize, u32)>> =
+        (0..elem_bytes).map(|alignment| ranked_elements(block, alignment, elem_bytes)).collect();
+
+    // like the layer, the extended dictionaries are only used where they do better
+    let plain = parse_gain(block, &dictionaries(&ranked, VALUES, elem_bytes), matches);
+    let ext = parse_gain(block, &dictionaries(&ranked, MAX_VALUES, elem_bytes), matches);
+
+    plain.max(ext)
+}
+
+/// Bytes saved by parsing the block greedily with the dictionaries, moving on to the next alignment after
+/// each miss or match.
+fn parse_gain(block: &[u8], dicts: &[Dictionary], matches: bool) -> i64 {
+    let elem_bytes = dicts[0].elem_bytes;
+    let mut matcher = if matches { Some(Matcher::new(block)) } else { None };
+
+    let mut saved = 0i64;
+    let mut miss_runs = 0i64;
+    let mut missing = false;
+    let mut index = 0;
+    let mut pos = 0;
+
+    while pos + elem_bytes <= block.len() {
+        let repeat = matcher.as_mut().and_then(|matcher| matcher.longest(pos, MAX_MATCH));
+
+        if let Some((_, len)) = repeat.filter(|(_, len)| compress::worth_matching(*len, elem_bytes)) {
+            saved += (len - compress::match_size(len)) as i64;
+            missing = false;
+            pos += len;
+            index = (index + len) % elem_bytes;
+            continue;
+        }
+
+        match dicts[index].get_index(&block[pos..pos + elem_bytes]) {
+            Some(elem_index) => {
+                let hit_bytes = if (elem_index as usize) < VALUES { 1 } else { 2 };
+                saved += elem_bytes as i64 - hit_bytes;
+                missing = false;
+                pos += elem_bytes;
+            }
+            None => {
+                miss_runs += !missing as i64;
+                missing = true;
+                pos += 1;
+                index = (index + 1) % elem_bytes;
+            }
+        }
+    }
+
+    let dict_bytes: usize = dicts.iter().map(|dict| utility::dict_size(dict.len(), elem_bytes)).sum();
+
+    saved - miss_runs - dict_bytes as i64
+}
+
+/// Elements seen often enough at the alignment, most common first.
+fn ranked_elements(block: &[u8], alignment: usize, elem_bytes: usize) -> Vec<(usize, u32)> {
+    let mut counter = ElemCounter::new(elem_bytes);
+
+    for elem in block.get(alignment..).unwrap_or(&[]).chunks_exact(elem_bytes) {
+        counter.increment(DictElem::key_of(elem));
+    }
+
+    let mut candidates = counter.at_least(MIN_OCCATIONS as u32);
+    // ties are ordered by the element, as the counts of wide elements come out of a hash map in any order
+    candidates.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    candidates
+}
+
+fn dictionaries(ranked: &[Vec<(usize, u32)>], len: usize, elem_bytes: usize) -> Vec<Dictionary> {
+    ranked
+        .iter()
+        .map(|candidates| {
+            let mut dict = Dictionary::with_elem_bytes(elem_bytes);
+
+            for (key, count) in candidates.iter().take(len) {
+                dict.push(DictElem::from_key(*key, elem_bytes, *count as u64));
+            }
+
+            dict
+        })
+        .collect()
+}
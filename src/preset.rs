@@ -0,0 +1,177 @@
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use crate::chunker;
+use crate::comp_structs::{dict_elem::DictElem, dictionary::Dictionary, elem_counter::ElemCounter, options::Options};
+use crate::compress;
+use crate::decompress;
+use crate::utility::{CHUNK_MAX_SIZE, DEBUG, MAX_ELEM_BYTES, MIN_ELEM_BYTES, MIN_OCCATIONS, VALUES};
+
+/// Marks a preset dictionary file.
+pub const MAGIC: [u8; 3] = *b"PCD";
+const VERSION: u8 = 1;
+
+/// Most layers a preset is trained for.
+const MAX_LAYERS: usize = 16;
+
+/// Dictionaries trained on sample files, one set for each layer. Chunks of a layer compressed with the
+/// preset can store their dictionaries as changes of the preset's, rather than in full.
+#[derive(Clone)]
+pub struct Preset {
+    pub elem_bytes: usize,
+    pub layers: Vec<Vec<Dictionary>>,
+}
+
+impl fmt::Debug for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Preset {:08x}: {} layers of {} Byte elements", self.id(), self.layers.len(), self.elem_bytes)
+    }
+}
+
+impl Preset {
+    /// Dictionaries of the layer with index `layer`, counted from the bottom, or none past the trained layers.
+    pub fn layer(&self, layer: usize) -> &[Dictionary] {
+        self.layers.get(layer).map_or(&[], |dicts| dicts.as_slice())
+    }
+
+    /// FNV-1a hash of the stored preset, which compressed files record to find the preset they need.
+    pub fn id(&self) -> u32 {
+        self.to_vec().iter().fold(0x811c_9dc5u32, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+    }
+
+    /// Writes the magic, version, element width and number of layers, followed by the dictionaries of each
+    /// alignment for every layer.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        out.push(self.elem_bytes as u8);
+        out.push(self.layers.len() as u8);
+
+        for dict in self.layers.iter().flatten() {
+            compress::write_dict(&mut out, dict);
+        }
+
+        out
+    }
+
+    pub fn read(data: &[u8]) -> Result<Self> {
+        if data.len() < MAGIC.len() + 3 || data[..MAGIC.len()] != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a preset dictionary file"));
+        }
+
+        if data[MAGIC.len()] != VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "unsupported preset version"));
+        }
+
+        let elem_bytes = data[MAGIC.len() + 1] as usize;
+        let nr_layers = data[MAGIC.len() + 2] as usize;
+
+        if !(MIN_ELEM_BYTES..=MAX_ELEM_BYTES).contains(&elem_bytes) {
+            return Err(Error::new(ErrorKind::InvalidData, "unsupported element width"));
+        }
+
+        let mut pos = MAGIC.len() + 3;
+        let mut layers: Vec<Vec<Dictionary>> = vec![];
+
+        for _ in 0..nr_layers {
+            let mut dicts: Vec<Dictionary> = vec![];
+
+            for _ in 0..elem_bytes {
+                dicts.push(decompress::get_dictionary(data, &mut pos, elem_bytes)?);
+            }
+
+            layers.push(dicts);
+        }
+
+        Ok(Preset { elem_bytes, layers })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Preset::read(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_vec())
+    }
+}
+
+/// Trains a preset on sample files, which should look like the files it will be used for.
+///
+/// Every layer keeps the elements most common across all samples, for each alignment within the chunks.
+/// The samples are then compressed through the layer with these dictionaries, and the next layer is trained
+/// on the result, until a layer no longer makes the samples smaller.
+pub fn train(samples: &[Vec<u8>], elem_bytes: usize) -> Result<Preset> {
+    let options = Options { elem_bytes, ..Options::default() };
+    let mut layers: Vec<Vec<Dictionary>> = vec![];
+    let mut inputs: Vec<Vec<u8>> = samples.to_vec();
+
+    while layers.len() < MAX_LAYERS {
+        let dicts = common_elements(&inputs, elem_bytes);
+
+        if dicts.iter().all(|dict| dict.is_empty()) {
+            break;
+        }
+
+        let mut outputs: Vec<Vec<u8>> = vec![];
+
+        for input in inputs.iter() {
+            let mut dict_collection = compress::generate_dict_collection(input, &options);
+            let mut output: Vec<u8> = vec![];
+            compress::encode_layer(input, &mut dict_collection, &mut output, &options, &dicts)?;
+            outputs.push(output);
+        }
+
+        let before: usize = inputs.iter().map(|input| input.len()).sum();
+        let after: usize = outputs.iter().map(|output| output.len()).sum();
+
+        if DEBUG {
+            println!("Preset layer {}: {} Bytes -> {} Bytes", layers.len() + 1, before, after);
+        }
+
+        if after >= before {
+            break;
+        }
+
+        layers.push(dicts);
+        inputs = outputs;
+    }
+
+    Ok(Preset { elem_bytes, layers })
+}
+
+/// The most common elements of each alignment within the chunks of all inputs, most common first.
+fn common_elements(inputs: &[Vec<u8>], elem_bytes: usize) -> Vec<Dictionary> {
+    let mut counters: Vec<ElemCounter> = (0..elem_bytes).map(|_| ElemCounter::new(elem_bytes)).collect();
+
+    let chunks = inputs.iter().flat_map(|input| {
+        let bounds = chunker::chunk_bounds(input, elem_bytes, CHUNK_MAX_SIZE as usize);
+        bounds.into_iter().map(move |bounds| &input[bounds])
+    });
+
+    for chunk in chunks {
+        for (alignment, counter) in counters.iter_mut().enumerate() {
+            for elem in chunk.get(alignment..).unwrap_or(&[]).chunks_exact(elem_bytes) {
+                counter.increment(DictElem::key_of(elem));
+            }
+        }
+    }
+
+    counters
+        .iter()
+        .map(|counter| {
+            let mut candidates = counter.at_least(MIN_OCCATIONS as u32);
+
+            // ties are ordered by the element itself to keep the output reproducible
+            candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            candidates.truncate(VALUES);
+
+            let mut dict = Dictionary::with_elem_bytes(elem_bytes);
+            for (key, count) in candidates {
+                dict.push(DictElem::from_key(key, elem_bytes, count as u64));
+            }
+
+            dict
+        })
+        .collect()
+}
@@ -0,0 +1,347 @@
+use std::io::prelude::*;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::comp_structs::{dictionary::Dictionary, token::Token};
+use crate::utility;
+use crate::utility::CHUNK_MAX_SIZE;
+
+/// Frequencies of each model add up to `1 << SCALE_BITS`.
+const SCALE_BITS: u32 = 12;
+const SCALE: u32 = 1 << SCALE_BITS;
+
+/// Lower bound of the coder state, which is renormalized a byte at a time.
+const RANS_L: u32 = 1 << 23;
+
+/// Symbols coded between rebuilding the frequencies of a model from its counts.
+const REBUILD_PERIOD: u32 = 1024;
+
+/// Counts are halved past this total, so models follow changes within the chunk.
+const COUNT_LIMIT: u32 = 1 << 16;
+
+/// Hit indexes and miss lengths of this value or above continue in the extra stream.
+const ESCAPE: u32 = 255;
+
+const KIND_HIT: u32 = 0;
+const KIND_MISS: u32 = 1;
+
+/// Adaptive order 0 model. Every symbol keeps a frequency of at least 1, so any symbol can be coded.
+struct Model {
+    counts: Vec<u32>,
+    total: u32,
+    freqs: Vec<u32>,
+    starts: Vec<u32>,
+    lookup: Vec<u16>,
+    until_rebuild: u32,
+}
+
+impl Model {
+    fn new(symbols: usize) -> Self {
+        let mut model = Model {
+            counts: vec![1; symbols],
+            total: symbols as u32,
+            freqs: vec![0; symbols],
+            starts: vec![0; symbols],
+            lookup: vec![0; SCALE as usize],
+            until_rebuild: REBUILD_PERIOD,
+        };
+
+        model.rebuild();
+        model
+    }
+
+    fn rebuild(&mut self) {
+        let symbols = self.counts.len() as u32;
+        let mut sum = 0;
+
+        for (freq, count) in self.freqs.iter_mut().zip(self.counts.iter()) {
+            *freq = 1 + (*count as u64 * (SCALE - symbols) as u64 / self.total as u64) as u32;
+            sum += *freq;
+        }
+
+        // the rounding remainder goes to the most common symbol
+        let mut most = 0;
+        for (symbol, count) in self.counts.iter().enumerate() {
+            if *count > self.counts[most] {
+                most = symbol;
+            }
+        }
+        self.freqs[most] += SCALE - sum;
+
+        let mut start = 0;
+        for symbol in 0..self.counts.len() {
+            self.starts[symbol] = start;
+
+            for slot in start..start + self.freqs[symbol] {
+                self.lookup[slot as usize] = symbol as u16;
+            }
+
+            start += self.freqs[symbol];
+        }
+    }
+
+    fn update(&mut self, symbol: u32) {
+        self.counts[symbol as usize] += 1;
+        self.total += 1;
+
+        if self.total > COUNT_LIMIT {
+            self.total = 0;
+
+            for count in self.counts.iter_mut() {
+                *count = (*count / 2).max(1);
+                self.total += *count;
+            }
+        }
+
+        self.until_rebuild -= 1;
+
+        if self.until_rebuild == 0 {
+            self.until_rebuild = REBUILD_PERIOD;
+            self.rebuild();
+        }
+    }
+}
+
+/// Collects the symbols of one stream, which are coded in reverse once the stream is complete.
+struct StreamWriter {
+    model: Model,
+    ranges: Vec<(u32, u32)>,
+}
+
+impl StreamWriter {
+    fn new(symbols: usize) -> Self {
+        StreamWriter { model: Model::new(symbols), ranges: vec![] }
+    }
+
+    fn put(&mut self, symbol: u32) {
+        let index = symbol as usize;
+        self.ranges.push((self.model.starts[index], self.model.freqs[index]));
+        self.model.update(symbol);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut out: Vec<u8> = vec![];
+        let mut x = RANS_L;
+
+        for (start, freq) in self.ranges.iter().rev() {
+            let x_max = ((RANS_L >> SCALE_BITS) << 8) * freq;
+
+            while x >= x_max {
+                out.push(x as u8);
+                x >>= 8;
+            }
+
+            x = ((x / freq) << SCALE_BITS) + (x % freq) + start;
+        }
+
+        // the bytes were produced last to first, and the final state is read first
+        out.extend([(x >> 24) as u8, (x >> 16) as u8, (x >> 8) as u8, x as u8]);
+        out.reverse();
+        out
+    }
+}
+
+struct StreamReader<'a> {
+    model: Model,
+    data: &'a [u8],
+    pos: usize,
+    x: u32,
+}
+
+impl<'a> StreamReader<'a> {
+    fn new(symbols: usize, data: &'a [u8]) -> Result<Self> {
+        if data.len() < 4 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "rans stream ended unexpectedly"));
+        }
+
+        let x = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+
+        Ok(StreamReader { model: Model::new(symbols), data, pos: 4, x })
+    }
+
+    fn get(&mut self) -> Result<u32> {
+        let slot = self.x & (SCALE - 1);
+        let symbol = self.model.lookup[slot as usize] as usize;
+
+        self.x = self.model.freqs[symbol] * (self.x >> SCALE_BITS) + slot - self.model.starts[symbol];
+
+        while self.x < RANS_L {
+            let byte = match self.data.get(self.pos) {
+                Some(byte) => *byte,
+                None => return Err(Error::new(ErrorKind::UnexpectedEof, "rans stream ended unexpectedly")),
+            };
+
+            self.x = (self.x << 8) | byte as u32;
+            self.pos += 1;
+        }
+
+        self.model.update(symbol as u32);
+        Ok(symbol as u32)
+    }
+}
+
+/// Codes the missed bytes of a pairs chunk which keeps them apart from its tokens, with an adaptive order 0 model.
+pub fn encode_literals(literals: &[u8]) -> Vec<u8> {
+    let mut stream = StreamWriter::new(256);
+
+    for byte in literals {
+        stream.put(*byte as u32);
+    }
+
+    stream.finish()
+}
+
+/// Reads the bytes coded by `encode_literals`, as many at a time as the miss tokens ask for.
+pub struct LiteralReader<'a>(StreamReader<'a>);
+
+impl<'a> LiteralReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        Ok(LiteralReader(StreamReader::new(256, data)?))
+    }
+
+    pub fn read(&mut self, len: usize, out: &mut Vec<u8>) -> Result<()> {
+        for _ in 0..len {
+            out.push(self.0.get()? as u8);
+        }
+
+        Ok(())
+    }
+}
+
+/// Codes the tokens of a chunk as five separate streams: token kinds, hit indexes, miss lengths, the
+/// extra bytes of large indexes and lengths, and the missed bytes themselves.
+///
+/// The result is the token count in 4 bytes, followed by each stream as its length in 4 bytes and its bytes.
+pub fn encode_tokens(tokens: &[Token], chunk: &[u8]) -> Vec<u8> {
+    let mut kinds = StreamWriter::new(2);
+    let mut hits = StreamWriter::new(256);
+    let mut lengths = StreamWriter::new(256);
+    let mut extra = StreamWriter::new(256);
+    let mut literals = StreamWriter::new(256);
+
+    for token in tokens {
+        match token {
+            Token::Hit { index, .. } => {
+                let index = *index as u32;
+                kinds.put(KIND_HIT);
+
+                if index < ESCAPE {
+                    hits.put(index);
+                } else {
+                    hits.put(ESCAPE);
+                    extra.put((index - ESCAPE) >> 8);
+                    extra.put((index - ESCAPE) & 0xFF);
+                }
+            }
+            Token::Miss(range) => {
+                let len = range.len() as u32;
+                kinds.put(KIND_MISS);
+
+                if len < ESCAPE {
+                    lengths.put(len);
+                } else {
+                    lengths.put(ESCAPE);
+                    for byte in len.to_be_bytes().iter() {
+                        extra.put(*byte as u32);
+                    }
+                }
+
+                for byte in chunk[range.clone()].iter() {
+                    literals.put(*byte as u32);
+                }
+            }
+            Token::Match { .. } | Token::ByteRun { .. } | Token::HitRun(_) => {
+                unreachable!("chunks coded with rANS are tokenized without matches or runs")
+            }
+        }
+    }
+
+    let mut out = utility::val_to_u8_vec(tokens.len(), 4);
+
+    for stream in [kinds, hits, lengths, extra, literals] {
+        let bytes = stream.finish();
+        out.extend(utility::val_to_u8_vec(bytes.len(), 4));
+        out.extend(bytes);
+    }
+
+    out
+}
+
+/// Decodes tokens written by `encode_tokens`, looking hits up in the dictionary of the current alignment.
+pub fn decode_tokens<W: Write>(writer: &mut W, dicts: &[Dictionary], data: &[u8]) -> Result<()> {
+    let mut pos = 0;
+    let token_count = read_u32(data, &mut pos)? as usize;
+
+    if token_count as u64 > CHUNK_MAX_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "more tokens than bytes in a chunk"));
+    }
+
+    let mut streams: Vec<&[u8]> = vec![];
+    for _ in 0..5 {
+        let len = read_u32(data, &mut pos)? as usize;
+
+        if data.len() - pos < len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "compressed data ended unexpectedly"));
+        }
+
+        streams.push(&data[pos..pos + len]);
+        pos += len;
+    }
+
+    let mut kinds = StreamReader::new(2, streams[0])?;
+    let mut hits = StreamReader::new(256, streams[1])?;
+    let mut lengths = StreamReader::new(256, streams[2])?;
+    let mut extra = StreamReader::new(256, streams[3])?;
+    let mut literals = StreamReader::new(256, streams[4])?;
+
+    let mut out: Vec<u8> = vec![];
+    let mut dict_index = 0;
+
+    for _ in 0..token_count {
+        if kinds.get()? == KIND_HIT {
+            let mut index = hits.get()?;
+
+            if index == ESCAPE {
+                index += (extra.get()? << 8) | extra.get()?;
+            }
+
+            if index as usize >= dicts[dict_index].len() {
+                return Err(Error::new(ErrorKind::InvalidData, "hit outside of dictionary"));
+            }
+
+            out.extend(dicts[dict_index].get(index as u16));
+        } else {
+            let mut len = lengths.get()?;
+
+            if len == ESCAPE {
+                len = 0;
+                for _ in 0..4 {
+                    len = (len << 8) | extra.get()?;
+                }
+            }
+
+            if len as u64 > CHUNK_MAX_SIZE {
+                return Err(Error::new(ErrorKind::InvalidData, "miss longer than a chunk"));
+            }
+
+            for _ in 0..len {
+                out.push(literals.get()? as u8);
+            }
+
+            // the missed bytes shift the alignment of the following elements
+            dict_index = (dict_index + len as usize) % dicts.len();
+        }
+    }
+
+    writer.write_all(&out)
+}
+
+fn read_u32(data: &[u8], pos: &mut usize) -> Result<u32> {
+    if data.len() - *pos < 4 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "compressed data ended unexpectedly"));
+    }
+
+    let value = utility::u8_vec_to_u64(&data[*pos..*pos + 4]) as u32;
+    *pos += 4;
+
+    Ok(value)
+}
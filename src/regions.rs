@@ -0,0 +1,180 @@
+use std::io::{Result, Write};
+
+use crate::comp_structs::{chunk_method::ChunkMethod, dictionary::Dictionary, token::Token};
+use crate::compress;
+use crate::utility::{SELECT_DICTS, SELECT_MARK};
+
+/// Smallest region given dictionaries of its own, so they can still fill up with common elements.
+const REGION_BYTES: usize = 1 << 16;
+
+/// Elements looked ahead in each dictionary when choosing which one to switch to.
+const LOOKAHEAD: usize = 4;
+
+/// Encodes a chunk with dictionaries for each alignment of the whole chunk and of each region of it, with
+/// explicit tokens selecting the dictionary of the following hits.
+///
+/// The payload is the number of dictionaries in 1 byte, the dictionaries, and the tokens. Misses do not
+/// change the selected dictionary, which starts out as the first one.
+pub fn compress_chunk<W: Write>(
+    chunk: &[u8],
+    elem_bytes: usize,
+    min_occurrences: u64,
+    writer: &mut W,
+) -> Result<(Vec<Dictionary>, u64, u64, u64)> {
+    let mut dicts = region_dicts(chunk, elem_bytes, min_occurrences);
+    let (mut tokens, hits, misses) = tokenize_chunk(&mut dicts, chunk);
+
+    let remaps: Vec<Vec<u16>> = dicts.iter_mut().map(|dict| dict.purge_unused()).collect();
+    compress::remap_hits(&mut tokens, &remaps);
+
+    // dictionaries which ended up without hits are left out, and the rest are numbered in order. The first
+    // one is always kept, so a chunk has at least one
+    let mut renumber = vec![0u8; dicts.len()];
+    let mut used: Vec<Dictionary> = vec![];
+
+    for (index, dict) in dicts.into_iter().enumerate() {
+        if index == 0 || !dict.is_empty() {
+            renumber[index] = used.len() as u8;
+            used.push(dict);
+        }
+    }
+
+    for token in tokens.iter_mut() {
+        if let Token::Hit { dict, .. } = token {
+            *dict = renumber[*dict as usize];
+        }
+    }
+
+    let mut payload: Vec<u8> = vec![used.len() as u8];
+
+    for dict in used.iter() {
+        compress::write_dict(&mut payload, dict);
+    }
+
+    let overhead = write_tokens(&mut payload, &tokens, chunk);
+    compress::write_chunk(writer, ChunkMethod::PairsSelect as u8, &payload)?;
+
+    Ok((used, hits, misses, overhead))
+}
+
+/// Dictionaries for each alignment of the whole chunk, followed by those of each region.
+fn region_dicts(chunk: &[u8], elem_bytes: usize, min_occurrences: u64) -> Vec<Dictionary> {
+    let max_regions = SELECT_DICTS / elem_bytes - 1;
+    let regions = (chunk.len() / REGION_BYTES).min(max_regions);
+
+    let mut dicts: Vec<Dictionary> = (0..elem_bytes as u64)
+        .map(|alignment| compress::generate_dict(chunk, alignment, elem_bytes, min_occurrences))
+        .collect();
+
+    // a single region would only repeat the dictionaries of the chunk
+    if regions > 1 {
+        for region in 0..regions {
+            let start = region * chunk.len() / regions;
+            let end = (region + 1) * chunk.len() / regions;
+
+            for alignment in 0..elem_bytes {
+                let offset = (start + alignment) as u64;
+                dicts.push(compress::generate_dict(&chunk[..end], offset, elem_bytes, min_occurrences));
+            }
+        }
+    }
+
+    for dict in dicts.iter_mut() {
+        dict.coverage = chunk.len() as u64;
+    }
+
+    dicts
+}
+
+/// Parses the chunk like the greedy parse, but when the selected dictionary misses, switches to the
+/// dictionary with the longest run of hits from there if that run is long enough to be kept.
+fn tokenize_chunk(dicts: &mut [Dictionary], chunk: &[u8]) -> (Vec<Token>, u64, u64) {
+    let mut tokens: Vec<Token> = vec![];
+    let mut hit_buf: Vec<u16> = vec![];
+    let mut missed = 0..0;
+
+    let mut selected = 0;
+    let mut hits = 0u64;
+    let mut misses = 0u64;
+    let mut has_read = 0usize;
+    let elem_bytes = dicts[0].elem_bytes;
+
+    while has_read + elem_bytes <= chunk.len() {
+        if let Some(elem_index) = dicts[selected].get_index(&chunk[has_read..has_read + elem_bytes]) {
+            hit_buf.push(elem_index);
+            has_read += elem_bytes;
+            continue;
+        }
+
+        let (h, m) = compress::manage_hits(&mut tokens, &mut hit_buf, &mut missed, selected, &mut dicts[selected]);
+        hits += h;
+        misses += m;
+
+        if let Some(next) = longest_run(dicts, &chunk[has_read..]) {
+            selected = next;
+            continue;
+        }
+
+        has_read += 1;
+        missed.end = has_read;
+        misses += 1;
+    }
+
+    let (h, m) = compress::manage_hits(&mut tokens, &mut hit_buf, &mut missed, selected, &mut dicts[selected]);
+    hits += h;
+    misses += m;
+
+    missed.end = chunk.len();
+    if missed.start != missed.end {
+        tokens.push(Token::Miss(missed));
+    }
+
+    (tokens, hits, misses)
+}
+
+/// The dictionary hitting the most elements in a row at the start of `rest`, if it hits at least two.
+fn longest_run(dicts: &[Dictionary], rest: &[u8]) -> Option<usize> {
+    let elem_bytes = dicts[0].elem_bytes;
+    let mut best: Option<(usize, usize)> = None;
+
+    for (index, dict) in dicts.iter().enumerate() {
+        let run = rest
+            .chunks_exact(elem_bytes)
+            .take(LOOKAHEAD)
+            .take_while(|elem| dict.get_index(elem).is_some())
+            .count();
+
+        // ties go to the lowest dictionary, which keeps the output reproducible
+        if run > 1 && best.is_none_or(|(_, best_run)| run > best_run) {
+            best = Some((index, run));
+        }
+    }
+
+    best.map(|(index, _)| index)
+}
+
+/// Writes the tokens like the pair encoding, with a select token before each hit in another dictionary.
+fn write_tokens(buf_write: &mut Vec<u8>, tokens: &[Token], chunk: &[u8]) -> u64 {
+    let mut selected = 0;
+    let mut overhead = 0;
+
+    for token in tokens {
+        match token {
+            Token::Hit { dict, index } => {
+                if *dict != selected {
+                    buf_write.push(SELECT_MARK | *dict);
+                    selected = *dict;
+                    overhead += 1;
+                }
+
+                overhead += compress::write_hit(buf_write, *index as usize);
+            }
+            Token::Miss(range) => overhead += compress::write_missed(buf_write, &chunk[range.clone()]),
+            Token::Match { .. } | Token::ByteRun { .. } | Token::HitRun(_) => {
+                unreachable!("regions are tokenized without matches or runs")
+            }
+        }
+    }
+
+    overhead
+}
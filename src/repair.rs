@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+use crate::comp_structs::bit_io::{BitReader, BitWriter};
+use crate::comp_structs::chunk_method::ChunkMethod;
+use crate::compress;
+use crate::utility;
+use crate::utility::{CHUNK_MAX_SIZE, DEBUG};
+
+/// Symbols below this are bytes, the rest refer to rules.
+const TERMINALS: u32 = 256;
+
+/// A pair has to occur this often to be replaced by a rule.
+const MIN_PAIR_COUNT: u32 = 4;
+
+/// A grammar where each rule expands to a pair of earlier symbols, and the sequence expands to the chunk.
+pub struct Grammar {
+    pub rules: Vec<(u32, u32)>,
+    pub sequence: Vec<u32>,
+}
+
+/// Builds a grammar for each chunk of up to `chunk_size` bytes, at most `CHUNK_MAX_SIZE`.
+pub fn compress_layer(path: &Path, data: &[u8], chunk_size: usize) -> Result<PathBuf> {
+    let (path_comp, mut writer) = compress::get_path_and_writer(path)?;
+    let mut rules = 0;
+
+    // an empty file still gets one chunk, like the pair encoding
+    let chunks: Vec<&[u8]> = if data.is_empty() {
+        vec![data]
+    } else {
+        data.chunks(chunk_size.clamp(1, CHUNK_MAX_SIZE as usize)).collect()
+    };
+
+    for chunk in chunks {
+        let grammar = build_grammar(chunk);
+        rules += grammar.rules.len();
+
+        compress::write_chunk(&mut writer, ChunkMethod::RePair as u8, &encode_grammar(&grammar))?;
+    }
+
+    writer.flush()?;
+
+    if DEBUG {
+        let size_after = path_comp.metadata()?.len();
+        println!("\nRE-PAIR LAYER RESULT:\n{} Bytes -> {} Bytes, {} rules", data.len(), size_after, rules);
+    }
+
+    Ok(path_comp)
+}
+
+/// Replaces frequent pairs of symbols with rules until no pair occurs `MIN_PAIR_COUNT` times.
+///
+/// Rather than one pair per pass, every pair at least half as frequent as the most frequent one is replaced
+/// in the same pass, which keeps the number of passes over the chunk low.
+pub fn build_grammar(chunk: &[u8]) -> Grammar {
+    let mut sequence: Vec<u32> = chunk.iter().map(|byte| *byte as u32).collect();
+    let mut rules: Vec<(u32, u32)> = vec![];
+
+    loop {
+        let mut candidates: Vec<(u64, u32)> = count_pairs(&sequence)
+            .into_iter()
+            .filter(|(_, count)| *count >= MIN_PAIR_COUNT)
+            .collect();
+
+        if candidates.is_empty() {
+            break;
+        }
+
+        // ties are ordered by the pair itself to keep the output reproducible
+        candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let threshold = (candidates[0].1 / 2).max(MIN_PAIR_COUNT);
+
+        let mut replace: HashMap<u64, u32> = HashMap::new();
+
+        for (key, _) in candidates.into_iter().take_while(|(_, count)| *count >= threshold) {
+            replace.insert(key, TERMINALS + rules.len() as u32);
+            rules.push(((key >> 32) as u32, key as u32));
+        }
+
+        sequence = replace_pairs(&sequence, &replace);
+    }
+
+    Grammar { rules, sequence }
+}
+
+fn pair_key(left: u32, right: u32) -> u64 {
+    ((left as u64) << 32) | right as u64
+}
+
+fn count_pairs(sequence: &[u32]) -> HashMap<u64, u32> {
+    let mut counts: HashMap<u64, u32> = HashMap::new();
+    let mut last_key = None;
+
+    for pair in sequence.windows(2) {
+        let key = pair_key(pair[0], pair[1]);
+
+        // in runs of the same symbol only every other pair can be replaced
+        if last_key == Some(key) {
+            last_key = None;
+            continue;
+        }
+
+        *counts.entry(key).or_insert(0) += 1;
+        last_key = Some(key);
+    }
+
+    counts
+}
+
+fn replace_pairs(sequence: &[u32], replace: &HashMap<u64, u32>) -> Vec<u32> {
+    let mut out = Vec::with_capacity(sequence.len());
+    let mut i = 0;
+
+    while i < sequence.len() {
+        if i + 1 < sequence.len() {
+            if let Some(symbol) = replace.get(&pair_key(sequence[i], sequence[i + 1])) {
+                out.push(*symbol);
+                i += 2;
+                continue;
+            }
+        }
+
+        out.push(sequence[i]);
+        i += 1;
+    }
+
+    out
+}
+
+/// Bits needed for every symbol of a grammar with `rules` rules.
+fn symbol_bits(rules: usize) -> u32 {
+    let symbols = TERMINALS as u64 + rules as u64;
+    64 - (symbols - 1).leading_zeros()
+}
+
+/// Writes the rule and sequence lengths as 4 bytes each, followed by every symbol packed to the same width.
+pub fn encode_grammar(grammar: &Grammar) -> Vec<u8> {
+    let bits = symbol_bits(grammar.rules.len());
+    let mut out = utility::val_to_u8_vec(grammar.rules.len(), 4);
+    out.extend(utility::val_to_u8_vec(grammar.sequence.len(), 4));
+
+    let mut writer = BitWriter::new();
+
+    for (left, right) in grammar.rules.iter() {
+        writer.write(*left, bits);
+        writer.write(*right, bits);
+    }
+
+    for symbol in grammar.sequence.iter() {
+        writer.write(*symbol, bits);
+    }
+
+    out.extend(writer.finish());
+    out
+}
+
+/// Expands the grammar of a chunk. A few rules can expand to far more than any chunk holds, so the expansion
+/// stops with an error once it is longer than `CHUNK_MAX_SIZE`.
+pub fn decompress_chunk<W: Write>(writer: &mut W, payload: &[u8]) -> Result<()> {
+    if payload.len() < 8 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "compressed data ended unexpectedly"));
+    }
+
+    let rule_count = utility::u8_vec_to_u64(&payload[0..4]) as usize;
+    let sequence_len = utility::u8_vec_to_u64(&payload[4..8]) as usize;
+    let bits = symbol_bits(rule_count);
+    let mut reader = BitReader::new(&payload[8..]);
+
+    let mut rules: Vec<(u32, u32)> = Vec::with_capacity(rule_count.min(payload.len()));
+
+    for index in 0..rule_count as u32 {
+        let left = reader.read(bits)?;
+        let right = reader.read(bits)?;
+
+        // rules may only refer to earlier rules, which keeps the expansion finite
+        if left >= TERMINALS + index || right >= TERMINALS + index {
+            return Err(Error::new(ErrorKind::InvalidData, "rule refers to an undefined symbol"));
+        }
+
+        rules.push((left, right));
+    }
+
+    let mut out: Vec<u8> = vec![];
+    let mut stack: Vec<u32> = vec![];
+
+    for _ in 0..sequence_len {
+        let symbol = reader.read(bits)?;
+
+        if symbol >= TERMINALS + rule_count as u32 {
+            return Err(Error::new(ErrorKind::InvalidData, "symbol refers to an undefined rule"));
+        }
+
+        stack.push(symbol);
+
+        while let Some(symbol) = stack.pop() {
+            if symbol < TERMINALS {
+                if out.len() as u64 == CHUNK_MAX_SIZE {
+                    return Err(Error::new(ErrorKind::InvalidData, "grammar expands past the largest chunk"));
+                }
+
+                out.push(symbol as u8);
+            } else {
+                let (left, right) = rules[(symbol - TERMINALS) as usize];
+                stack.push(right);
+                stack.push(left);
+            }
+        }
+    }
+
+    writer.write_all(&out)
+}
@@ -0,0 +1,142 @@
+use std::io::Result;
+
+use crate::comp_structs::{dict_elem::DictElem, dictionary::Dictionary, options::Options, token::Token};
+use crate::compress;
+use crate::utility::{DEBUG, VALUES};
+
+/// Rounds of pruning and reordering tried on the dictionaries of a chunk.
+const ROUNDS: usize = 4;
+
+/// Dictionaries of a chunk, the chunk encoded with them, and the savings they were estimated to give.
+pub struct Selection {
+    pub dicts: Vec<Dictionary>,
+    pub encoded: Vec<u8>,
+    pub estimated: f64,
+}
+
+/// Improves the dictionaries a chunk was encoded with, by the bytes each entry actually saves.
+///
+/// Every round parses the chunk, credits each hit with the bytes it saves over missing the element, and
+/// charges each entry the bytes it takes in the dictionary. Entries that do not pay for themselves are
+/// dropped and the rest are ordered by their savings, so the best entries get the one byte hits. A round is
+/// only kept if the chunk gets smaller.
+pub fn refine(dicts: Vec<Dictionary>, encoded: Vec<u8>, chunk: &[u8], options: &Options) -> Result<Selection> {
+    let mut best = Selection { dicts, encoded, estimated: 0.0 };
+
+    for _ in 0..ROUNDS {
+        let savings = entry_savings(&best.dicts, chunk, options);
+        let (mut dicts, estimated) = select(&best.dicts, &savings);
+
+        if same_entries(&dicts, &best.dicts) {
+            break;
+        }
+
+        let mut encoded: Vec<u8> = vec![];
+        compress::compress_chunk(&mut dicts, chunk, &mut encoded, options)?;
+
+        if encoded.len() >= best.encoded.len() {
+            break;
+        }
+
+        best = Selection { dicts, encoded, estimated };
+    }
+
+    if DEBUG && best.estimated > 0.0 {
+        println!(
+            "Cost based selection: estimated {:.0} Bytes saved, achieved {} Bytes",
+            best.estimated,
+            chunk.len() as i64 - best.encoded.len() as i64
+        );
+    }
+
+    Ok(best)
+}
+
+/// Bytes saved by each entry of each dictionary when parsing the chunk, before paying for the entry itself.
+/// The chunk is parsed with the same matches and runs as when it is encoded.
+///
+/// A hit is credited with what the chunk would lose if it were a miss instead. At the end of a run that is
+/// the element less the hit. Inside a run it also splits the run with a new miss header, and can leave a
+/// single hit on either side, which would be demoted to a miss as well. A hit followed by a hit run is also
+/// credited with the repeats, less the run token.
+fn entry_savings(dicts: &[Dictionary], chunk: &[u8], options: &Options) -> Vec<Vec<f64>> {
+    let mut dicts = dicts.to_vec();
+    let (tokens, _, _) = compress::tokenize_chunk(&mut dicts, chunk, compress::allowed_tokens(options));
+    let mut savings: Vec<Vec<f64>> = dicts.iter().map(|dict| vec![0.0; dict.len()]).collect();
+
+    let mut start = 0;
+
+    while start < tokens.len() {
+        // collect the run of hits starting here
+        let mut run: Vec<(usize, usize, f64)> = vec![];
+
+        while let Some(Token::Hit { dict, index }) = tokens.get(start + run.len()) {
+            let elem_bytes = dicts[*dict as usize].elem_bytes as f64;
+            let hit_bytes = if (*index as usize) < VALUES { 1.0 } else { 2.0 };
+            run.push((*dict as usize, *index as usize, elem_bytes - hit_bytes));
+        }
+
+        let len = run.len();
+
+        for (pos, (dict, index, gain)) in run.iter().enumerate() {
+            let saved = if len <= 3 && (len == 2 || pos == 1) {
+                // without this hit nothing is left of the run, and the misses around it merge
+                run.iter().map(|(_, _, gain)| gain).sum::<f64>() + 1.0
+            } else if pos == 0 || pos == len - 1 {
+                *gain
+            } else {
+                let left = if pos == 1 { run[0].2 } else { 0.0 };
+                let right = if pos == len - 2 { run[len - 1].2 } else { 0.0 };
+                gain + 1.0 + left + right
+            };
+
+            savings[*dict][*index] += saved;
+        }
+
+        if let (Some((dict, index, _)), Some(Token::HitRun(repeats))) = (run.last(), tokens.get(start + len)) {
+            savings[*dict][*index] += (repeats * dicts[*dict].elem_bytes) as f64 - 1.0;
+        }
+
+        start += len.max(1);
+    }
+
+    savings
+}
+
+/// Keeps the entries that save more than they cost, ordered by their net savings, and returns them with the
+/// total savings estimated for the chunk.
+fn select(dicts: &[Dictionary], savings: &[Vec<f64>]) -> (Vec<Dictionary>, f64) {
+    let mut selected: Vec<Dictionary> = vec![];
+    let mut estimated = 0.0;
+
+    for (dict, dict_savings) in dicts.iter().zip(savings.iter()) {
+        let entry_bytes = dict.elem_bytes as f64;
+
+        let mut entries: Vec<(usize, f64)> = dict_savings
+            .iter()
+            .enumerate()
+            .map(|(index, saved)| (index, saved - entry_bytes))
+            .filter(|(_, net)| *net > 0.0)
+            .collect();
+
+        // ties keep their old order, which keeps the output reproducible
+        entries.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+
+        let mut new_dict = Dictionary::with_elem_bytes(dict.elem_bytes);
+        new_dict.coverage = dict.coverage;
+
+        for (index, net) in entries {
+            let elem = &dict.elems[index];
+            new_dict.push(DictElem::new(elem.bytes(), elem.occurance));
+            estimated += net;
+        }
+
+        selected.push(new_dict);
+    }
+
+    (selected, estimated)
+}
+
+fn same_entries(a: &[Dictionary], b: &[Dictionary]) -> bool {
+    a.iter().zip(b.iter()).all(|(a, b)| a.to_vec() == b.to_vec())
+}
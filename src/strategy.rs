@@ -0,0 +1,128 @@
+use std::io::{Error, ErrorKind, Result, Write};
+
+use crate::comp_structs::{chunk_method::ChunkMethod, options::Options};
+use crate::compress;
+use crate::decompress;
+use crate::rans;
+use crate::utility;
+use crate::utility::CHUNK_MAX_SIZE;
+
+/// Shortest run written as a run by the run-length encoding, shorter ones stay among the literal bytes.
+const MIN_RLE_RUN: usize = 4;
+
+/// Encodes the chunk with each of the encodings that need no dictionaries, and returns the smallest written
+/// chunk if it beats the `pairs_len` bytes of the chunk written with pair tokens.
+///
+/// The chunk can be stored as it is, with `runs` also as runs and literal bytes, and with `entropy_chunks` also
+/// as plain bytes coded with rANS.
+pub fn smaller_encoding(chunk: &[u8], options: &Options, pairs_len: usize) -> Result<Option<Vec<u8>>> {
+    let mut candidates = vec![(ChunkMethod::Stored, chunk.to_vec())];
+
+    if options.runs {
+        candidates.push((ChunkMethod::Rle, encode_rle(chunk)));
+    }
+
+    if options.entropy_chunks {
+        let mut coded: Vec<u8> = vec![];
+        utility::write_varint(&mut coded, chunk.len() as u64);
+        coded.extend(rans::encode_literals(chunk));
+        candidates.push((ChunkMethod::EntropyBytes, coded));
+    }
+
+    let mut best: Option<Vec<u8>> = None;
+
+    for (method, payload) in candidates {
+        let mut buf: Vec<u8> = vec![];
+        compress::write_chunk(&mut buf, method as u8, &payload)?;
+
+        if buf.len() < best.as_ref().map_or(pairs_len, |best| best.len()) {
+            best = Some(buf);
+        }
+    }
+
+    Ok(best)
+}
+
+/// Writes the chunk as items starting with a varint, holding the item length above its shortest length and,
+/// in the lowest bit, if it is a run. A run is followed by the repeated byte, and literals by their bytes.
+pub fn encode_rle(chunk: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = vec![];
+    let mut literals = 0..0;
+    let mut pos = 0;
+
+    while pos < chunk.len() {
+        let run = chunk[pos..].iter().take_while(|byte| **byte == chunk[pos]).count();
+
+        if run < MIN_RLE_RUN {
+            pos += run;
+            literals.end = pos;
+            continue;
+        }
+
+        if !literals.is_empty() {
+            utility::write_varint(&mut out, ((literals.len() - 1) << 1) as u64);
+            out.extend(&chunk[literals]);
+        }
+
+        utility::write_varint(&mut out, ((run - MIN_RLE_RUN) << 1 | 1) as u64);
+        out.push(chunk[pos]);
+
+        pos += run;
+        literals = pos..pos;
+    }
+
+    if !literals.is_empty() {
+        utility::write_varint(&mut out, ((literals.len() - 1) << 1) as u64);
+        out.extend(&chunk[literals]);
+    }
+
+    out
+}
+
+/// Decodes the runs and literal bytes written by `encode_rle`.
+pub fn decode_rle<W: Write>(writer: &mut W, data: &[u8]) -> Result<()> {
+    let mut out: Vec<u8> = vec![];
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let item = decompress::read_varint(data, &mut pos)?;
+        let is_run = item & 1 == 1;
+        let len = (item >> 1).saturating_add(if is_run { MIN_RLE_RUN } else { 1 } as u64);
+
+        if out.len() as u64 + len > CHUNK_MAX_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "runs longer than a chunk"));
+        }
+
+        let len = len as usize;
+
+        if is_run {
+            let byte = *data.get(pos).ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "run without its byte"))?;
+            out.resize(out.len() + len, byte);
+            pos += 1;
+        } else {
+            if data.len() - pos < len {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "compressed data ended unexpectedly"));
+            }
+
+            out.extend(&data[pos..pos + len]);
+            pos += len;
+        }
+    }
+
+    writer.write_all(&out)
+}
+
+/// Decodes the plain bytes of a chunk coded with rANS, following their count as a varint.
+pub fn decode_entropy_bytes<W: Write>(writer: &mut W, data: &[u8]) -> Result<()> {
+    let mut pos = 0;
+    let len = decompress::read_varint(data, &mut pos)?;
+
+    if len > CHUNK_MAX_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "more bytes than a chunk holds"));
+    }
+
+    let mut out: Vec<u8> = Vec::with_capacity(len as usize);
+    rans::LiteralReader::new(&data[pos..])?.read(len as usize, &mut out)?;
+
+    writer.write_all(&out)
+}
@@ -1,4 +1,4 @@
-use std::io::{BufReader, BufWriter};
+use std::io::BufWriter;
 use std::path::Path;
 
 pub const DEBUG: bool = false;
@@ -6,16 +6,50 @@ pub const DETAILED_DEBUG: bool = false;
 pub const DEBUG_DICT: bool = false;
 
 pub const ELEM_BYTES: usize = 2;
+pub const MIN_ELEM_BYTES: usize = 2;
+pub const MAX_ELEM_BYTES: usize = 4;
 pub const ELEM_HALF: usize = ELEM_BYTES / 2;
 pub const ELEM_BITS: u8 = (ELEM_BYTES * 8) as u8;
 pub const NR_ELEMS: usize = 1 << ELEM_BITS;
 pub const VALUE_BITS: u8 = ((ELEM_HALF * 8) - 1) as u8;
 pub const VALUES: usize = 1 << VALUE_BITS;
 pub const VALUES_HALF: usize = VALUES / 2;
+// long misses from format version 4 on hold the low 3 bits of the length above the short misses, followed by
+// the rest as a varint. Before they held the number of length bytes, followed by the length
+pub const LONG_MISS_BITS: u8 = 3;
+// extended hits use two bytes, the first byte marks the hit and carries the top bits of the index
+pub const EXT_HIT_MARK: u8 = 0b00010000;
+pub const EXT_HIT_BITS: u8 = 12;
+pub const EXT_VALUES: usize = 1 << EXT_HIT_BITS;
+pub const MAX_VALUES: usize = VALUES + EXT_VALUES;
+// select tokens of chunks with explicit dictionaries carry the dictionary in the low 5 bits. They take the bits of
+// the run tokens, so those chunks can not be marked to have runs, and decoding one that is fails
+pub const SELECT_MARK: u8 = 0b00100000;
+pub const SELECT_DICTS: usize = 1 << 5;
+// match tokens of chunks marked to have them repeat earlier bytes of the chunk. The low 3 bits hold the length
+// above the shortest match, or 7 followed by the rest in 2 bytes, and the distance back follows in 3 bytes
+pub const MATCH_MARK: u8 = 0b00001000;
+pub const MIN_MATCH: usize = 8;
+pub const MAX_MATCH: usize = MIN_MATCH + 7 + 0xFFFF;
+// run tokens of chunks marked to have them, in the place of select tokens. A byte run repeats the byte following
+// the token, a hit run repeats the element of the last hit. The low 4 bits hold the count above the shortest
+// run, or 15 followed by the rest in 3 bytes
+pub const BYTE_RUN_MARK: u8 = 0b00100000;
+pub const HIT_RUN_MARK: u8 = 0b00110000;
+pub const MIN_BYTE_RUN: usize = 16;
+pub const MIN_HIT_RUN: usize = 4;
+// dictionary length byte telling that the real length follows as 2 bytes
+pub const EXT_DICT_LEN: u8 = 0xFF;
 pub const CHUNK_MAX_SIZE: u64 = 790000;
 pub const MIN_OCCATIONS: u64 = 4;
+// layers stop at this count, or once a layer saves less than this part of its input
+pub const MAX_LAYERS: u8 = u8::MAX;
+pub const MIN_LAYER_GAIN: f64 = 0.001;
+// compression levels from the fastest to the smallest, the default level gives the default options
+pub const MIN_LEVEL: u8 = 1;
+pub const MAX_LEVEL: u8 = 9;
+pub const DEFAULT_LEVEL: u8 = 4;
 
-pub type Reader = BufReader<std::fs::File>;
 pub type Writer = BufWriter<std::fs::File>;
 
 pub fn u8_to_string(val: u8) -> String {
@@ -41,6 +75,27 @@ pub fn val_to_u8_vec(value: usize, bytes: u8) -> Vec<u8> {
     u8_vec
 }
 
+/// Writes `value` in 7 bit groups, the lowest first, with the top bit set on every byte but the last.
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    buf.push(value as u8);
+}
+
+/// Bytes of the varint at the start of `data`.
+pub fn varint_bytes(data: &[u8]) -> usize {
+    data.iter().take_while(|byte| *byte & 0x80 != 0).count() + 1
+}
+
+/// Number of bytes used to store a dictionary of `len` elements, including its length.
+pub fn dict_size(len: usize, elem_bytes: usize) -> usize {
+    let len_bytes = if len > VALUES { 3 } else { 1 };
+    len_bytes + len * elem_bytes
+}
+
 pub fn u8_vec_to_u32(s: &[u8; 4]) -> u32 {
     let o1 = (s[0] as u32) << (8 * 3);
     let o2 = (s[1] as u32) << (8 * 2);
@@ -50,12 +105,12 @@ pub fn u8_vec_to_u32(s: &[u8; 4]) -> u32 {
     o1 | o2 | o3 | o4
 }
 
-pub fn u8_vec_to_u64(s: &Vec<u8>) -> u64 {
+pub fn u8_vec_to_u64(s: &[u8]) -> u64 {
     let mut val: u64 = 0;
     let last = s.len() - 1;
 
-    for i in 0..=last {
-        val |= (s[i] as u64) << (8 * (last - i));
+    for (i, byte) in s.iter().enumerate() {
+        val |= (*byte as u64) << (8 * (last - i));
     }
 
     val
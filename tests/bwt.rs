@@ -0,0 +1,121 @@
+use pcomp::bwt;
+use pcomp::comp_structs::chunk_method::ChunkMethod;
+use pcomp::comp_structs::{header::Header, options::Options};
+use pcomp::utility;
+use pcomp::{compress, decompress};
+
+mod common;
+
+/// Encodes one layer, returning it with the method byte of each chunk.
+fn encode(data: &[u8], options: &Options) -> (Vec<u8>, Vec<u8>) {
+    let mut dicts = compress::generate_dict_collection(data, options);
+    let mut encoded: Vec<u8> = vec![];
+    compress::encode_layer(data, &mut dicts, &mut encoded, options, &[]).unwrap();
+
+    let header = Header::new(1, options.elem_bytes);
+    let mut previous = vec![];
+    let mut decoded: Vec<u8> = vec![];
+    let mut methods = vec![];
+    let mut pos = 0;
+
+    while pos < encoded.len() {
+        methods.push(encoded[pos + utility::varint_bytes(&encoded[pos..])]);
+        pos += decompress::decompress_chunk(&mut decoded, &encoded[pos..], &header, &mut previous).unwrap();
+    }
+
+    assert!(decoded == data);
+    (encoded, methods)
+}
+
+#[test]
+fn transforms_a_known_block() {
+    // the last column of the sorted suffixes of "banana" and an end marker is "annb$aa", with the end marker
+    // in row 4, which move-to-front turns into 97 110 0 99 2 0
+    assert_eq!(bwt::encode(b"banana"), (vec![97, 110, 0, 99, 2, 0], 4));
+    assert_eq!(bwt::decode(&[97, 110, 0, 99, 2, 0], 4).unwrap(), b"banana");
+}
+
+#[test]
+fn blocks_round_trip() {
+    let text = common::text(790_000);
+    let records = common::records(100_000, 3);
+    let zeros = vec![0u8; 700_000];
+    let steps: Vec<u8> = (0..200_000).map(|i| (i * 7 % 256) as u8).collect();
+
+    for data in [&text[..], &records[..], &zeros[..], &steps[..], b"a", b""] {
+        let (transformed, primary) = bwt::encode(data);
+        assert!(bwt::decode(&transformed, primary).unwrap() == data, "{} bytes changed", data.len());
+    }
+}
+
+#[test]
+fn primary_indexes_at_the_edges() {
+    // only the empty block sorts after its end marker in the first row
+    assert_eq!(bwt::encode(b""), (vec![], 0));
+    assert_eq!(bwt::decode(&[], 0).unwrap(), b"");
+
+    // blocks sorting first and last among their suffixes, which puts the end marker in the second and the last
+    // row
+    for (block, primary) in [(&b"a"[..], 1), (b"ab", 1), (b"ba", 2), (b"aaaa", 4)] {
+        let (transformed, found) = bwt::encode(block);
+        assert_eq!(found, primary);
+        assert_eq!(bwt::decode(&transformed, primary).unwrap(), block);
+    }
+}
+
+#[test]
+fn damaged_blocks_are_errors() {
+    let (transformed, _) = bwt::encode(b"abracadabra");
+
+    // a primary index past the end of the block, and the first row, which only holds the end marker
+    assert!(bwt::decode(&transformed, 200).is_err());
+    assert!(bwt::decode(&transformed, 0).is_err());
+}
+
+#[test]
+fn text_chunks_are_transformed() {
+    let text = common::text(400_000);
+    let options = Options { bwt: true, chunk_size: 1 << 16, ..Options::default() };
+
+    let (plain, methods) = encode(&text, &Options { bwt: false, ..options.clone() });
+    assert!(methods.iter().all(|method| *method != ChunkMethod::Bwt as u8), "{:?}", methods);
+
+    let (transformed, methods) = encode(&text, &options);
+    assert!(methods.iter().all(|method| *method == ChunkMethod::Bwt as u8), "{:?}", methods);
+    assert!(transformed.len() < plain.len(), "{} >= {}", transformed.len(), plain.len());
+
+    // chunks that gain nothing from the transform are left as they are
+    let mut state = 7u32;
+    let noise: Vec<u8> = (0..100_000)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+
+    let (_, methods) = encode(&noise, &options);
+    assert!(methods.iter().all(|method| *method != ChunkMethod::Bwt as u8), "{:?}", methods);
+}
+
+#[test]
+fn transformed_files_round_trip() {
+    let dir = common::test_dir("bwt-files");
+    let text = common::text(400_000);
+    common::round_trip_with(&dir, "text.txt", &text, &Options { bwt: true, ..Options::default() });
+}
+
+#[test]
+fn nested_transforms_are_errors() {
+    let header = Header::new(1, 2);
+    let (mut chunk, _) = encode(&common::text(1000), &Options { bwt: true, ..Options::default() });
+
+    // wrapping the chunk in a transformed chunk twice, with the primary index 0
+    for _ in 0..2 {
+        let inner = std::mem::take(&mut chunk);
+        utility::write_varint(&mut chunk, 2 + inner.len() as u64);
+        chunk.extend([ChunkMethod::Bwt as u8, 0]);
+        chunk.extend(inner);
+    }
+
+    assert!(decompress::decompress_chunk(&mut vec![], &chunk, &header, &mut vec![]).is_err());
+}
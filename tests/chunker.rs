@@ -0,0 +1,51 @@
+use pcomp::chunker;
+use pcomp::utility::CHUNK_MAX_SIZE;
+
+mod common;
+
+fn assert_covers(bounds: &[std::ops::Range<usize>], len: usize) {
+    assert_eq!(bounds.first().unwrap().start, 0);
+    assert_eq!(bounds.last().unwrap().end, len);
+    assert!(bounds.windows(2).all(|pair| pair[0].end == pair[1].start));
+    assert!(bounds.iter().all(|chunk| chunk.len() <= CHUNK_MAX_SIZE as usize));
+}
+
+#[test]
+fn uniform_data_gets_full_chunks() {
+    let data = common::text(2_000_000);
+    let bounds = chunker::chunk_bounds(&data, 2, CHUNK_MAX_SIZE as usize);
+
+    assert_covers(&bounds, data.len());
+    assert_eq!(bounds.len(), 3);
+    assert!(bounds[..2].iter().all(|chunk| chunk.len() == CHUNK_MAX_SIZE as usize));
+
+    assert_eq!(chunker::chunk_bounds(&[], 2, CHUNK_MAX_SIZE as usize), vec![0..0]);
+}
+
+#[test]
+fn shifts_in_content_start_new_chunks() {
+    // text, then records made of high bytes, then records made of low bytes
+    let mut data = common::text(300_000);
+    data.extend(common::records(200_000, 4).iter().map(|byte| byte | 0x80));
+    data.extend(common::records(250_000, 3).iter().map(|byte| byte & 0x0F));
+
+    let block = 1 << 15;
+
+    for elem_bytes in [2usize, 3] {
+        let bounds = chunker::chunk_bounds(&data, elem_bytes, CHUNK_MAX_SIZE as usize);
+        assert_covers(&bounds, data.len());
+
+        // cuts fall on whole blocks, so the block holding a shift can get a chunk of its own
+        let cuts: Vec<usize> = bounds[1..].iter().map(|chunk| chunk.start).collect();
+        let near = |cut: usize, shift: usize| cut + block > shift && cut < shift + block;
+
+        for shift in [300_000, 500_000] {
+            assert!(cuts.iter().any(|cut| near(*cut, shift)), "{:?}", cuts);
+        }
+
+        assert!(cuts.iter().all(|cut| near(*cut, 300_000) || near(*cut, 500_000)), "{:?}", cuts);
+    }
+
+    let dir = common::test_dir("chunker-shifts");
+    common::round_trip(&dir, "shifts.bin", &data);
+}
@@ -0,0 +1,76 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pcomp::comp_structs::options::Options;
+use pcomp::{compress, decompress};
+
+/// Creates an empty directory for a single test so tests can run in parallel.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pcomp-test-{}-{}", std::process::id(), name));
+
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+/// Text like data from a small vocabulary, with many pairs sharing the same count.
+pub fn text(size: usize) -> Vec<u8> {
+    let mut state = 12345u32;
+    let mut out = Vec::with_capacity(size);
+
+    while out.len() < size {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let word = (state >> 16) % 400;
+        out.extend(format!("w{} ", word).bytes());
+    }
+
+    out.truncate(size);
+    out
+}
+
+/// Fixed size records, made up of a few fields that each take a small set of values.
+pub fn records(size: usize, record_bytes: usize) -> Vec<u8> {
+    let mut state = 54321u32;
+    let mut out = Vec::with_capacity(size);
+
+    while out.len() < size {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let id = (state >> 16) % 48;
+        out.extend((0..record_bytes).map(|i| (id as usize * 31 + i * 7) as u8));
+    }
+
+    out.truncate(size);
+    out
+}
+
+/// Compresses and decompresses `data` through files in `dir`, returning the compressed size.
+pub fn round_trip(dir: &Path, name: &str, data: &[u8]) -> u64 {
+    round_trip_with(dir, name, data, &Options::default())
+}
+
+/// Same as `round_trip`, compressing with the given options.
+pub fn round_trip_with(dir: &Path, name: &str, data: &[u8], options: &Options) -> u64 {
+    let path = dir.join(name);
+    fs::write(&path, data).unwrap();
+
+    let comp = compress::run(&path, options).unwrap();
+    let comp_len = comp.metadata().unwrap().len();
+    fs::remove_file(&path).unwrap();
+
+    let decomp = decompress::run_with_preset(&comp, options.preset.as_ref()).unwrap();
+    assert_eq!(decomp, path);
+    assert!(fs::read(&decomp).unwrap() == data, "{} changed in the round trip", name);
+
+    fs::remove_file(&comp).unwrap();
+    fs::remove_file(&decomp).unwrap();
+
+    let left: Vec<_> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert!(left.is_empty(), "temporary files left behind: {:?}", left);
+
+    comp_len
+}
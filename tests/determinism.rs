@@ -0,0 +1,25 @@
+use std::fs;
+
+use pcomp::comp_structs::options::Options;
+use pcomp::compress;
+
+mod common;
+
+#[test]
+fn compressing_twice_gives_identical_bytes() {
+    let dir = common::test_dir("determinism");
+    let path = dir.join("input.txt");
+    fs::write(&path, common::text(300_000)).unwrap();
+
+    let mut outputs = vec![];
+
+    for _ in 0..3 {
+        let comp = compress::run(&path, &Options::default()).unwrap();
+        outputs.push(fs::read(&comp).unwrap());
+        fs::remove_file(&comp).unwrap();
+    }
+
+    assert!(outputs.iter().all(|out| *out == outputs[0]));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
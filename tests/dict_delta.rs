@@ -0,0 +1,42 @@
+use pcomp::comp_structs::{chunk_method::DELTA_DICTS, header::Header, options::Options};
+use pcomp::utility::{self, CHUNK_MAX_SIZE};
+use pcomp::{compress, decompress};
+
+#[test]
+fn later_chunks_store_dictionaries_as_changes() {
+    let chunk_len = CHUNK_MAX_SIZE as usize;
+    let alphabet = b"abcdefghij";
+    let mut state = 777u32;
+
+    // small alphabets keep the dictionaries below 128 entries, the second chunk finds the same pairs as the
+    // first and the third shares most of them
+    let data: Vec<u8> = (0..3 * chunk_len - 1)
+        .map(|i| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let shift = if i < 2 * chunk_len { 0 } else { 2 };
+            alphabet[shift + (state >> 16) as usize % 8]
+        })
+        .collect();
+
+    let options = Options::default();
+    let mut dicts = compress::generate_dict_collection(&data, &options);
+    let mut encoded: Vec<u8> = vec![];
+    compress::encode_layer(&data, &mut dicts, &mut encoded, &options, &[]).unwrap();
+
+    let header = Header::new(1, options.elem_bytes);
+    let mut previous = vec![];
+    let mut decoded: Vec<u8> = vec![];
+    let mut methods = vec![];
+    let mut pos = 0;
+
+    while pos < encoded.len() {
+        // the method byte follows the length of the chunk
+        methods.push(encoded[pos + utility::varint_bytes(&encoded[pos..])]);
+        pos += decompress::decompress_chunk(&mut decoded, &encoded[pos..], &header, &mut previous).unwrap();
+    }
+
+    assert!(decoded == data);
+    assert_eq!(methods.len(), 3);
+    assert_eq!(methods[0] & DELTA_DICTS, 0);
+    assert!(methods[1..].iter().all(|method| method & DELTA_DICTS != 0), "{:?}", methods);
+}
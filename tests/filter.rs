@@ -0,0 +1,140 @@
+use std::fs;
+use std::io::ErrorKind;
+use pcomp::comp_structs::{header::Header, options::Options};
+use pcomp::compress;
+use pcomp::filter::{self, Filter};
+
+mod common;
+
+/// Little endian floats of a slowly changing measurement, as written by telemetry dumps.
+fn telemetry(count: usize) -> Vec<u8> {
+    (0..count).flat_map(|i| (20.0 + (i as f32 * 0.001).sin() * 5.0 + (i % 7) as f32 * 0.01).to_le_bytes()).collect()
+}
+
+#[test]
+fn filters_revert_on_any_length() {
+    let filters = [
+        Filter::Delta { stride: 1 },
+        Filter::Delta { stride: 4 },
+        Filter::Transpose { width: 3 },
+        Filter::Transpose { width: 4 },
+        Filter::Branch,
+    ];
+
+    for len in [0usize, 1, 2, 3, 4, 5, 9, 1000] {
+        let data: Vec<u8> = (0..len).map(|i| (i * 37 % 251) as u8 ^ 0xE8).collect();
+
+        for filter in filters {
+            assert!(filter.revert(&filter.apply(&data)) == data, "{} on {} bytes", filter, len);
+        }
+
+        assert!(filter::revert_all(&filters, &filter::apply_all(&filters, &data)) == data);
+    }
+}
+
+#[test]
+fn branch_filter_reverts_on_overlapping_branches() {
+    // the reported case, an opcode looking ahead into the displacement of the branch after it
+    let data = [0xE8, 0x01, 0xE8, 0xF9, 0xFE, 0x00, 0x00, 0x00];
+    assert!(Filter::Branch.revert(&Filter::Branch.apply(&data)) == data);
+
+    // random code dense in opcodes and top bytes, so branches overlap in every way
+    let mut state = 31u32;
+    let mut next = || {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        state >> 16
+    };
+
+    for _ in 0..2000 {
+        let len = next() as usize % 64;
+        let data: Vec<u8> = (0..len).map(|_| [0xE8, 0xE9, 0x00, 0xFF, next() as u8][next() as usize % 5]).collect();
+        assert!(Filter::Branch.revert(&Filter::Branch.apply(&data)) == data, "{:02X?}", data);
+    }
+}
+
+#[test]
+fn transpose_groups_the_bytes_of_records() {
+    let data = [1, 2, 3, 4, 5, 6, 7];
+    assert_eq!(Filter::Transpose { width: 3 }.apply(&data), vec![1, 4, 2, 5, 3, 6, 7]);
+}
+
+#[test]
+fn branch_filter_makes_calls_to_one_target_equal() {
+    let target = 10_000u32;
+    let mut code: Vec<u8> = vec![];
+
+    for call in 0..4u32 {
+        let next = call * 8 + 5;
+        code.push(0xE8);
+        code.extend(target.wrapping_sub(next).to_le_bytes());
+        code.extend([0x90, 0x90, 0x90]);
+    }
+
+    let filtered = Filter::Branch.apply(&code);
+
+    for call in filtered.chunks(8) {
+        assert_eq!(call[1..5], target.to_le_bytes());
+    }
+
+    assert!(Filter::Branch.revert(&filtered) == code);
+}
+
+#[test]
+fn filters_are_parsed_and_stored() {
+    assert_eq!(Filter::parse("delta:4").unwrap(), Filter::Delta { stride: 4 });
+    assert_eq!(Filter::parse("transpose:8").unwrap(), Filter::Transpose { width: 8 });
+    assert_eq!(Filter::parse("bcj").unwrap(), Filter::Branch);
+
+    for bad in ["delta", "delta:0", "transpose:1", "transpose:256", "bcj:2", "lz"] {
+        assert!(Filter::parse(bad).is_err(), "{}", bad);
+    }
+
+    let mut header = Header::new(3, 2);
+    header.filters = vec![Filter::Transpose { width: 4 }, Filter::Delta { stride: 1 }];
+    let bytes = header.to_vec().unwrap();
+    assert_eq!(Header::read(&bytes).unwrap(), (header.clone(), bytes.len()));
+
+    // a header cut within its filters is not read as a shorter one
+    assert!(Header::read(&bytes[..bytes.len() - 1]).is_err());
+
+    // the count of the filters takes a byte
+    header.filters = vec![Filter::Branch; 255];
+    assert!(Header::read(&header.to_vec().unwrap()).is_ok());
+    header.filters.push(Filter::Branch);
+    assert_eq!(header.to_vec().unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn too_many_filters_are_rejected() {
+    let dir = common::test_dir("filter-count");
+    let path = dir.join("text.txt");
+    fs::write(&path, common::text(10_000)).unwrap();
+
+    // rejected before anything is written next to the input
+    let options = Options { filters: vec![Filter::Delta { stride: 1 }; 256], ..Options::default() };
+    assert_eq!(compress::run(&path, &options).unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[test]
+fn filtered_files_round_trip() {
+    let dir = common::test_dir("filter-round-trip");
+    let floats = telemetry(100_000);
+
+    let plain_len = common::round_trip(&dir, "floats.bin", &floats);
+    let filters = vec![Filter::Delta { stride: 4 }, Filter::Transpose { width: 4 }];
+    let filtered = Options { filters, ..Options::default() };
+    let filtered_len = common::round_trip_with(&dir, "floats.bin", &floats, &filtered);
+    assert!(filtered_len < plain_len, "{} >= {}", filtered_len, plain_len);
+
+    // also with nothing left for the layers to compress
+    let no_layers = Options { max_layers: 0, ..filtered.clone() };
+    common::round_trip_with(&dir, "floats.bin", &floats, &no_layers);
+
+    let branch = Options { filters: vec![Filter::Branch], huffman: true, ..Options::default() };
+
+    for len in [0usize, 1, 5, 790_001] {
+        let data: Vec<u8> = (0..len).map(|i| if i % 9 == 0 { 0xE8 } else { (i % 7) as u8 }).collect();
+        common::round_trip_with(&dir, &format!("edge_{}.bin", len), &data, &branch);
+    }
+}
@@ -0,0 +1,93 @@
+use pcomp::comp_structs::chunk_method::{ChunkMethod, RUNS};
+use pcomp::comp_structs::{dictionary::Dictionary, header::Header};
+use pcomp::repair::{self, Grammar};
+use pcomp::decompress;
+use pcomp::utility::{self, CHUNK_MAX_SIZE, SELECT_MARK};
+
+mod common;
+
+/// Decodes a single chunk written for a file of the given format version.
+fn decode_chunk(version: u8, chunk: &[u8]) -> Vec<u8> {
+    let mut header = Header::new(1, 2);
+    header.version = version;
+
+    let mut out: Vec<u8> = vec![];
+    let mut previous: Vec<Dictionary> = vec![];
+
+    let len = decompress::decompress_chunk(&mut out, chunk, &header, &mut previous).unwrap();
+    assert_eq!(len, chunk.len());
+
+    out
+}
+
+#[test]
+fn chunks_of_older_versions_still_decode() {
+    let data = common::text(1000);
+
+    // total length in 4 bytes, no dictionaries and a miss with a 2 byte length
+    let mut old = vec![0, 0, 0x03, 0xF2, 0, 0, 0, 2, 0x03, 0xE8];
+    old.extend(&data);
+    assert_eq!(old.len(), 0x3F2);
+    assert!(decode_chunk(3, &old) == data);
+
+    // the rest of the chunk as a varint, and a miss of 64 + 936 bytes, 0 in the token and 936 >> 3 after it
+    let mut new = vec![0xED, 0x07, 0, 0, 0, 0, 117];
+    new.extend(&data);
+    assert_eq!(new.len() - 2, 0x3ED);
+    assert!(decode_chunk(4, &new) == data);
+
+    // a chunk longer than its data is damaged
+    assert!(decompress::decompress_chunk(&mut vec![], &new[..500], &Header::new(1, 2), &mut vec![]).is_err());
+}
+
+#[test]
+fn misses_of_every_length_round_trip() {
+    let dir = common::test_dir("format-misses");
+
+    // pieces of text between random bytes, so the chunks hold misses of all lengths
+    let mut data: Vec<u8> = vec![];
+    let mut state = 99u32;
+
+    for len in (1..400).step_by(7) {
+        data.extend(common::text(len));
+
+        for _ in 0..len {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            data.push((state >> 24) as u8);
+        }
+    }
+
+    common::round_trip(&dir, "small.bin", &data);
+}
+
+#[test]
+fn select_chunks_can_not_have_runs() {
+    // one dictionary holding "ab", a select token for it and a hit, which with runs would read as a byte run
+    let payload = [1, 1, b'a', b'b', SELECT_MARK, 0x80];
+    let mut chunk: Vec<u8> = vec![];
+    utility::write_varint(&mut chunk, 1 + payload.len() as u64);
+    chunk.push(ChunkMethod::PairsSelect as u8);
+    chunk.extend(payload);
+
+    assert_eq!(decode_chunk(Header::new(1, 2).version, &chunk), b"ab");
+
+    let method_at = utility::varint_bytes(&chunk);
+    chunk[method_at] |= RUNS;
+    assert!(decompress::decompress_chunk(&mut vec![], &chunk, &Header::new(1, 2), &mut vec![]).is_err());
+}
+
+#[test]
+fn grammars_can_not_expand_past_a_chunk() {
+    // each rule doubles the one before it, so the last one expands to 2^40 bytes
+    let mut rules: Vec<(u32, u32)> = vec![(b'a' as u32, b'a' as u32)];
+    rules.extend((256..295).map(|rule| (rule, rule)));
+    let bomb = Grammar { sequence: vec![295], rules };
+    assert!(repair::decompress_chunk(&mut vec![], &repair::encode_grammar(&bomb)).is_err());
+
+    // a chunk of the largest size still expands
+    let rules: Vec<(u32, u32)> = vec![(b'a' as u32, b'a' as u32)];
+    let full = Grammar { sequence: vec![256; CHUNK_MAX_SIZE as usize / 2], rules };
+    let mut out: Vec<u8> = vec![];
+    repair::decompress_chunk(&mut out, &repair::encode_grammar(&full)).unwrap();
+    assert_eq!(out.len() as u64, CHUNK_MAX_SIZE);
+}
@@ -0,0 +1,132 @@
+use std::fs;
+use std::path::Path;
+
+use pcomp::comp_structs::{header::Header, options::Options};
+use pcomp::{compress, decompress, prediction};
+
+mod common;
+
+/// Compresses `data` and returns the number of layers written, checking that it decompresses again.
+fn layers_written(dir: &Path, name: &str, data: &[u8], options: &Options) -> u8 {
+    let path = dir.join(name);
+    fs::write(&path, data).unwrap();
+
+    let comp = compress::run(&path, options).unwrap();
+    let (header, _) = Header::read(&fs::read(&comp).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let decomp = decompress::run(&comp).unwrap();
+    assert!(fs::read(&decomp).unwrap() == data, "{} changed in the round trip", name);
+
+    fs::remove_file(&comp).unwrap();
+    fs::remove_file(&decomp).unwrap();
+
+    header.layers
+}
+
+/// Bytes of a linear congruential generator, which no layer can make smaller.
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491u32;
+
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn max_layers_limits_the_layers() {
+    let dir = common::test_dir("layers-max");
+
+    // with pairs alone every layer halves a run of zeros, so the limit is what stops it
+    let zeros = vec![0u8; 200_000];
+    let pairs_only = Options { matches: false, runs: false, ..Options::default() };
+    let unlimited = layers_written(&dir, "zeros.bin", &zeros, &pairs_only);
+    assert!(unlimited > 3);
+
+    for max_layers in [0u8, 1, 3] {
+        let options = Options { max_layers, ..pairs_only.clone() };
+        assert_eq!(layers_written(&dir, "zeros.bin", &zeros, &options), max_layers);
+    }
+}
+
+#[test]
+fn min_gain_drops_small_layers() {
+    let dir = common::test_dir("layers-min-gain");
+    let text = common::text(400_000);
+
+    let sizes: Vec<u64> = (1..=2u8)
+        .map(|max_layers| {
+            let options = Options { max_layers, min_gain: 0.0, ..Options::default() };
+            common::round_trip_with(&dir, "text.txt", &text, &options)
+        })
+        .collect();
+
+    assert!(sizes[1] < sizes[0]);
+
+    // a layer saving a little less than required is dropped, the first layer saves far more
+    let second_gain = (sizes[0] - sizes[1]) as f64 / sizes[0] as f64;
+    let options = Options { min_gain: second_gain * 1.5, ..Options::default() };
+    assert_eq!(layers_written(&dir, "text.txt", &text, &options), 1);
+
+    let no_gain_enough = Options { min_gain: 0.99, ..Options::default() };
+    assert_eq!(layers_written(&dir, "text.txt", &text, &no_gain_enough), 0);
+
+    assert_eq!(layers_written(&dir, "noise.bin", &noise(100_000), &Options::default()), 0);
+}
+
+#[test]
+fn prediction_tells_hopeless_layers() {
+    assert!(prediction::layer_gain(&noise(300_000), 2, true) <= 0);
+    assert_eq!(prediction::layer_gain(&[], 2, true), 0);
+
+    for (data, elem_bytes) in [(common::text(300_000), 2), (common::records(300_000, 4), 4)] {
+        assert!(prediction::layer_gain(&data, elem_bytes, false) > data.len() as i64 / 10);
+    }
+
+    // wide elements with equal counts give the same prediction every time
+    for elem_bytes in [3, 4] {
+        let data = common::text(300_000);
+        let first = prediction::layer_gain(&data, elem_bytes, true);
+        assert!((0..8).all(|_| prediction::layer_gain(&data, elem_bytes, true) == first));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn pipes_are_read_once() {
+    let dir = common::test_dir("layers-pipe");
+    let path = dir.join("pipe");
+    let data = common::text(300_000);
+    assert!(std::process::Command::new("mkfifo").arg(&path).status().unwrap().success());
+
+    // a pipe has no length and can only be read once, for the prediction, the layer and the recode together
+    let writer = {
+        let (path, data) = (path.clone(), data.clone());
+        std::thread::spawn(move || fs::write(path, data).unwrap())
+    };
+    let comp = compress::run(&path, &Options::default()).unwrap();
+    writer.join().unwrap();
+
+    let (header, _) = Header::read(&fs::read(&comp).unwrap()).unwrap();
+    assert!(header.layers > 1, "{} layers", header.layers);
+    fs::remove_file(&path).unwrap();
+
+    let decomp = decompress::run(&comp).unwrap();
+    assert!(fs::read(&decomp).unwrap() == data);
+}
+
+#[test]
+fn layers_are_measured_without_min_gain() {
+    let dir = common::test_dir("layers-measured");
+
+    // noise around a short run of zeros, which the prediction takes for pairs, but a run token saves
+    let mut data = noise(300_000);
+    data[150_000..150_500].fill(0);
+
+    let measured = Options { min_gain: 0.0, ..Options::default() };
+    assert!(prediction::layer_gain(&data, measured.elem_bytes, measured.matches) < 0);
+    assert_eq!(layers_written(&dir, "sparse.bin", &data, &measured), 1);
+}
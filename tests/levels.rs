@@ -0,0 +1,84 @@
+use pcomp::comp_structs::options::Options;
+use pcomp::utility::{DEFAULT_LEVEL, MAX_LEVEL, MIN_LEVEL};
+
+mod common;
+
+#[test]
+fn every_level_round_trips() {
+    let dir = common::test_dir("levels");
+
+    let mut sparse = common::text(100_000);
+    sparse.resize(250_000, 0);
+    sparse.extend(common::text(60_000));
+
+    let corpora: [(&str, Vec<u8>); 5] = [
+        ("empty.bin", vec![]),
+        ("byte.bin", vec![7]),
+        ("text.txt", common::text(300_000)),
+        ("records.bin", common::records(200_000, 4)),
+        ("sparse.bin", sparse),
+    ];
+
+    for level in MIN_LEVEL..=MAX_LEVEL {
+        let options = Options::with_level(level).unwrap();
+
+        for (name, data) in corpora.iter() {
+            let comp_len = common::round_trip_with(&dir, name, data, &options);
+            assert!(data.len() < 1000 || comp_len < data.len() as u64, "level {} on {}", level, name);
+        }
+    }
+}
+
+#[test]
+fn levels_trade_time_for_size() {
+    let dir = common::test_dir("levels-sizes");
+    let text = common::text(300_000);
+
+    let size = |level: u8| common::round_trip_with(&dir, "text.txt", &text, &Options::with_level(level).unwrap());
+    assert!(size(1) > size(DEFAULT_LEVEL));
+    assert!(size(DEFAULT_LEVEL) > size(MAX_LEVEL));
+    assert!(size(MAX_LEVEL - 1) > size(MAX_LEVEL));
+}
+
+#[test]
+fn rans_levels_keep_smaller_pair_chunks() {
+    let dir = common::test_dir("levels-rans");
+
+    // a counter in steps of a quarter, which the pair encoding with its matches and literals takes better than
+    // rANS
+    let floats: Vec<u8> = (0..200_000).flat_map(|i| (1000.0 + i as f32 / 4.0).to_le_bytes()).collect();
+
+    let size = |level: u8| common::round_trip_with(&dir, "floats.bin", &floats, &Options::with_level(level).unwrap());
+    let (before, after) = (size(5), size(6));
+    assert!(after <= before, "{} > {}", after, before);
+}
+
+#[test]
+fn highest_level_keeps_the_matches_of_the_default() {
+    let dir = common::test_dir("levels-matches");
+
+    // a block of noise repeated, which only matches can make smaller
+    let mut state = 5u32;
+    let block: Vec<u8> = (0..100_000)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        })
+        .collect();
+    let data = block.repeat(4);
+
+    let size = |level: u8| common::round_trip_with(&dir, "repeated.bin", &data, &Options::with_level(level).unwrap());
+    let default = size(DEFAULT_LEVEL);
+    assert!(default < data.len() as u64 / 2);
+    assert!(size(MAX_LEVEL) <= default);
+}
+
+#[test]
+fn default_level_gives_the_default_options() {
+    let level = Options::with_level(DEFAULT_LEVEL).unwrap();
+    assert_eq!(format!("{:?}", level), format!("{:?}", Options::default()));
+
+    for level in [0, MAX_LEVEL + 1] {
+        assert!(Options::with_level(level).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use pcomp::comp_structs::{chunk_method::LITERALS, header::Header, options::Options};
+use pcomp::utility;
+use pcomp::{compress, decompress};
+
+mod common;
+
+/// Text with words of random letters, so most of it is missed at the bottom layer.
+fn noisy_text(len: usize) -> Vec<u8> {
+    let mut state = 4242u32;
+    let text = common::text(len);
+
+    text.iter()
+        .enumerate()
+        .map(|(i, byte)| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            if i % 3 == 0 { b'a' + (state >> 24) as u8 % 16 } else { *byte }
+        })
+        .collect()
+}
+
+/// Encodes one layer, returning it with the method byte of each chunk.
+fn encode(data: &[u8], options: &Options) -> (Vec<u8>, Vec<u8>) {
+    let mut dicts = compress::generate_dict_collection(data, options);
+    let mut encoded: Vec<u8> = vec![];
+    compress::encode_layer(data, &mut dicts, &mut encoded, options, &[]).unwrap();
+
+    let mut methods = vec![];
+    let mut pos = 0;
+
+    while pos < encoded.len() {
+        let prefix = utility::varint_bytes(&encoded[pos..]);
+        methods.push(encoded[pos + prefix]);
+        pos += decompress::decompress_chunk(&mut vec![], &encoded[pos..], &Header::new(1, 2), &mut vec![]).unwrap();
+    }
+
+    (encoded, methods)
+}
+
+#[test]
+fn chunks_code_their_misses_apart() {
+    let data = noisy_text(300_000);
+
+    let (with, methods) = encode(&data, &Options::default());
+    assert!(methods.iter().all(|method| method & LITERALS != 0), "{:?}", methods);
+
+    let (without, methods) = encode(&data, &Options { literals: false, ..Options::default() });
+    assert!(methods.iter().all(|method| method & LITERALS == 0), "{:?}", methods);
+    assert!(with.len() < without.len(), "{} >= {}", with.len(), without.len());
+
+    let mut decoded: Vec<u8> = vec![];
+    decompress::decompress_chunk(&mut decoded, &with, &Header::new(1, 2), &mut vec![]).unwrap();
+    assert!(decoded == data);
+
+    // chunks with nothing to gain keep their misses between the tokens
+    let (_, methods) = encode(&common::records(100_000, 4), &Options::default());
+    assert!(methods.iter().all(|method| method & LITERALS == 0), "{:?}", methods);
+}
+
+#[test]
+fn files_with_literals_round_trip() {
+    let dir = common::test_dir("literals");
+    let noisy = noisy_text(400_000);
+
+    let with = common::round_trip(&dir, "noisy.txt", &noisy);
+    let no_literals = Options { literals: false, ..Options::default() };
+    let without = common::round_trip_with(&dir, "noisy.txt", &noisy, &no_literals);
+    assert!(with < without, "{} >= {}", with, without);
+
+    for elem_bytes in [2usize, 3, 4] {
+        let options = Options { elem_bytes, ..Options::default() };
+        common::round_trip_with(&dir, &format!("noisy_{}.txt", elem_bytes), &noisy[..200_000], &options);
+    }
+}
+
+#[test]
+fn damaged_literals_are_reported() {
+    let data = noisy_text(100_000);
+    let (encoded, _) = encode(&data, &Options::default());
+
+    // the literals end with the chunk, so a shorter chunk runs out of them
+    let prefix = utility::varint_bytes(&encoded);
+    let mut short = vec![];
+    utility::write_varint(&mut short, (encoded.len() - prefix - 100) as u64);
+    short.extend(&encoded[prefix..encoded.len() - 100]);
+
+    assert!(decompress::decompress_chunk(&mut vec![], &short, &Header::new(1, 2), &mut vec![]).is_err());
+}
@@ -0,0 +1,74 @@
+use pcomp::comp_structs::options::Options;
+use pcomp::matcher::Matcher;
+
+mod common;
+
+fn noise(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn matcher_finds_the_longest_repeat() {
+    let mut data = noise(1000, 1);
+    data.extend_from_within(100..400);
+    data.extend_from_within(200..250);
+
+    let mut matcher = Matcher::new(&data);
+    assert_eq!(matcher.longest(0, usize::MAX), None);
+    assert_eq!(matcher.longest(1000, usize::MAX), Some((900, 300)));
+    assert_eq!(matcher.longest(1000, 20), Some((900, 20)));
+    assert_eq!(matcher.longest(1300, usize::MAX), Some((200, 50)));
+
+    // a run repeats the byte before it
+    let zeros = vec![0u8; 100];
+    assert_eq!(Matcher::new(&zeros).longest(1, usize::MAX), Some((1, 99)));
+}
+
+#[test]
+fn repeated_blocks_shrink_in_one_layer() {
+    let dir = common::test_dir("matches-blocks");
+
+    // blocks no dictionary of pairs helps with, repeated apart within the chunk
+    let block = noise(40_000, 7);
+    let mut data = block.clone();
+    data.extend(noise(20_000, 9));
+    data.extend(&block);
+
+    let one_layer = Options { max_layers: 1, ..Options::default() };
+    let with_matches = common::round_trip_with(&dir, "blocks.bin", &data, &one_layer);
+    assert!(with_matches < 61_000, "{}", with_matches);
+
+    let without = Options { matches: false, ..one_layer };
+    assert!(common::round_trip_with(&dir, "blocks.bin", &data, &without) > 100_000);
+}
+
+#[test]
+fn matches_round_trip_at_their_limits() {
+    let dir = common::test_dir("matches-limits");
+
+    for elem_bytes in [2usize, 3, 4] {
+        let options = Options { elem_bytes, ..Options::default() };
+
+        // runs longer than the longest match, short periods matching into themselves and matches just
+        // around the shortest length, at every alignment
+        let mut data = vec![0u8; 200_000];
+        data.extend((0..30_000u32).map(|i| (i % 3) as u8 + 1));
+
+        for len in 7..30 {
+            let piece = noise(len, len as u32);
+            data.extend(&piece);
+            data.push(len as u8);
+            data.extend(&piece);
+        }
+
+        data.extend(noise(5, 3));
+        common::round_trip_with(&dir, &format!("limits_{}.bin", elem_bytes), &data, &options);
+    }
+}
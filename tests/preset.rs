@@ -0,0 +1,113 @@
+use std::fs;
+
+use pcomp::comp_structs::options::Options;
+use pcomp::preset::{self, Preset};
+use pcomp::{compress, decompress};
+
+mod common;
+
+/// Small files cut from a longer text, so they share a vocabulary but not their contents.
+fn samples() -> Vec<Vec<u8>> {
+    common::text(300_000).chunks(3_000).map(|sample| sample.to_vec()).collect()
+}
+
+#[test]
+fn preset_shrinks_small_files() {
+    let dir = common::test_dir("preset-small-files");
+    let samples = samples();
+    let preset = preset::train(&samples[..80], 2).unwrap();
+    assert!(!preset.layers.is_empty());
+
+    let with_preset = Options { preset: Some(preset), ..Options::default() };
+    let mut plain_total = 0;
+    let mut preset_total = 0;
+
+    for (index, sample) in samples[80..].iter().enumerate() {
+        let name = format!("sample_{}.txt", index);
+        plain_total += common::round_trip(&dir, &name, sample);
+        preset_total += common::round_trip_with(&dir, &name, sample, &with_preset);
+    }
+
+    assert!(preset_total < plain_total, "{} >= {}", preset_total, plain_total);
+}
+
+/// JSON records of about 100 bytes, with the same keys and values from small sets.
+fn json_samples(count: usize) -> Vec<Vec<u8>> {
+    let mut state = 777u32;
+    let mut next = |range: u32| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (state >> 16) % range
+    };
+
+    (0..count)
+        .map(|id| {
+            format!(
+                "{{\"id\": {}, \"user\": \"w{}\", \"status\": \"{}\", \"score\": {}, \"tags\": [\"t{}\", \"t{}\"]}}\n",
+                10_000 + id,
+                next(400),
+                ["active", "pending", "closed"][next(3) as usize],
+                next(100),
+                next(20),
+                next(20)
+            )
+            .into_bytes()
+        })
+        .collect()
+}
+
+#[test]
+fn preset_shrinks_tiny_files() {
+    let dir = common::test_dir("preset-tiny-files");
+    let samples = json_samples(300);
+    assert!(samples.iter().all(|sample| (80..120).contains(&sample.len())));
+
+    let preset = preset::train(&samples[..250], 2).unwrap();
+    let with_preset = Options { preset: Some(preset), ..Options::default() };
+    let mut plain_total = 0;
+    let mut preset_total = 0;
+
+    // chunks this small gain the most from preset dictionaries they keep whole, as those are stored as the same
+    // as the previous ones
+    for (index, sample) in samples[250..].iter().enumerate() {
+        let name = format!("sample_{}.json", index);
+        plain_total += common::round_trip(&dir, &name, sample);
+        preset_total += common::round_trip_with(&dir, &name, sample, &with_preset);
+    }
+
+    assert!(preset_total < plain_total * 2 / 3, "{} >= 2/3 of {}", preset_total, plain_total);
+}
+
+#[test]
+fn preset_is_needed_to_decompress() {
+    let dir = common::test_dir("preset-needed");
+    let samples = samples();
+    let preset = preset::train(&samples[..40], 2).unwrap();
+    let other = preset::train(&samples[40..], 2).unwrap();
+
+    // the stored preset reads back as the same preset
+    let preset_path = dir.join("dict.pcd");
+    preset.save(&preset_path).unwrap();
+    let loaded = Preset::load(&preset_path).unwrap();
+    assert_eq!(loaded.id(), preset.id());
+    assert_ne!(other.id(), preset.id());
+    fs::remove_file(&preset_path).unwrap();
+
+    let path = dir.join("sample.txt");
+    fs::write(&path, &samples[90]).unwrap();
+
+    let options = Options { preset: Some(loaded), ..Options::default() };
+    let comp = compress::run(&path, &options).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(decompress::run(&comp).is_err());
+    assert!(decompress::run_with_preset(&comp, Some(&other)).is_err());
+
+    let decomp = decompress::run_with_preset(&comp, Some(&preset)).unwrap();
+    assert!(fs::read(&decomp).unwrap() == samples[90]);
+
+    // a preset for other elements can not be used
+    let wide = Options { elem_bytes: 3, ..options };
+    assert!(compress::run(&decomp, &wide).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,486 @@
+mod common;
+
+use pcomp::comp_structs::chunk_method::ChunkMethod;
+use pcomp::comp_structs::{dict_elem::DictElem, dictionary::Dictionary, header::Header, token::Token};
+use pcomp::comp_structs::options::{Mode, Options};
+use pcomp::{compress, decompress, huffman, optimal_parse, rans, regions, repair, selection};
+use pcomp::utility::{self, CHUNK_MAX_SIZE, EXT_DICT_LEN, EXT_HIT_MARK, LONG_MISS_BITS, MIN_OCCATIONS, VALUES_HALF};
+
+/// Reads a varint of the layer at `pos`, moving past it.
+fn varint(data: &[u8], pos: &mut usize) -> u64 {
+    let len = utility::varint_bytes(&data[*pos..]);
+    let value = data[*pos..*pos + len].iter().rev().fold(0, |value, byte| value << 7 | (byte & 0x7F) as u64);
+    *pos += len;
+    value
+}
+
+/// Counts the dictionaries with a two byte length and the two byte hits of a layer of pair chunks, which hold
+/// their own dictionaries and only hits and misses.
+fn extended_counts(layer: &[u8], elem_bytes: usize) -> (usize, usize) {
+    let (mut dicts, mut hits) = (0, 0);
+    let mut pos = 0;
+
+    while pos < layer.len() {
+        let end = varint(layer, &mut pos) as usize + pos;
+        assert!(layer[pos] == ChunkMethod::Pairs as u8, "method byte {:#x}", layer[pos]);
+        pos += 1;
+
+        for _ in 0..elem_bytes {
+            let mut len = layer[pos] as usize;
+            pos += 1;
+
+            if len == EXT_DICT_LEN as usize {
+                len = (layer[pos] as usize) << 8 | layer[pos + 1] as usize;
+                pos += 2;
+                dicts += 1;
+            }
+
+            pos += len * elem_bytes;
+        }
+
+        while pos < end {
+            let byte = layer[pos];
+            pos += 1;
+
+            if byte & 0b10000000 != 0 {
+                continue;
+            } else if byte & 0b11110000 == EXT_HIT_MARK {
+                pos += 1;
+                hits += 1;
+            } else if byte & 0b01000000 != 0 {
+                pos += (byte & 0b00111111) as usize;
+            } else {
+                let high = varint(layer, &mut pos);
+                pos += ((high << LONG_MISS_BITS) as usize | (byte & 0b111) as usize) + VALUES_HALF;
+            }
+        }
+
+        assert_eq!(pos, end);
+    }
+
+    (dicts, hits)
+}
+
+/// Text from a vocabulary of words with all the letters, so far more pairs are frequent than in `common::text`.
+fn wide_text(len: usize) -> Vec<u8> {
+    let mut state = 2024u32;
+    let mut next = |modulo: u32| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (state >> 16) % modulo
+    };
+
+    let words: Vec<Vec<u8>> = (0..3000).map(|_| (0..3 + next(4)).map(|_| b'a' + next(26) as u8).collect()).collect();
+    let mut out: Vec<u8> = Vec::with_capacity(len);
+
+    while out.len() < len {
+        out.extend(&words[next(words.len() as u32) as usize]);
+        out.push(b' ');
+    }
+
+    out.truncate(len);
+    out
+}
+
+/// Two byte values in `segments` that alternate between two sets, which share most of their values. Together the
+/// sets have more values than a dictionary holds, but each fits one.
+fn alternating_values(len: usize, segments: usize) -> Vec<u8> {
+    let (shared, own) = (100, 28);
+    let mut state = 99u32;
+    let mut next = |modulo: u32| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (state >> 16) % modulo
+    };
+
+    let values: Vec<u16> = (0..shared + 2 * own).map(|_| next(1 << 16) as u16).collect();
+    let mut out: Vec<u8> = Vec::with_capacity(len);
+
+    while out.len() < len {
+        let pick = next(shared + own);
+        let set = (out.len() * segments / len % 2) as u32;
+        let value = if pick < shared { values[pick as usize] } else { values[(pick + own * set) as usize] };
+        out.extend(value.to_le_bytes());
+    }
+
+    out.truncate(len);
+    out
+}
+
+/// Encodes a chunk with region dictionaries and checks that it decodes, returning what the encoder did.
+fn region_chunk(chunk: &[u8]) -> (Vec<Dictionary>, u64, u64, u64) {
+    let mut encoded: Vec<u8> = vec![];
+    let result = regions::compress_chunk(chunk, 2, MIN_OCCATIONS, &mut encoded).unwrap();
+
+    let mut decoded: Vec<u8> = vec![];
+    let len = decompress::decompress_chunk(&mut decoded, &encoded, &Header::new(1, 2), &mut vec![]).unwrap();
+    assert!(len == encoded.len() && decoded == chunk, "{} bytes changed", chunk.len());
+
+    result
+}
+
+/// Bytes of a linear congruential generator, which nothing makes smaller.
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 7u32;
+
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn text_uses_extended_dictionaries() {
+    let dir = common::test_dir("round-trip-text");
+    let data = wide_text(400_000);
+
+    // a vocabulary this large has many more frequent pairs than fit in one byte hits. The layer is written
+    // with hits and misses only, so its tokens can be told apart
+    let options = Options { matches: false, runs: false, literals: false, ..Options::default() };
+    let mut dicts = compress::generate_dict_collection(&data, &options);
+    let mut layer: Vec<u8> = vec![];
+    compress::encode_layer(&data, &mut dicts, &mut layer, &options, &[]).unwrap();
+
+    let (ext_dicts, ext_hits) = extended_counts(&layer, options.elem_bytes);
+    assert!(ext_dicts > 0 && ext_hits > 0, "{} extended dictionaries, {} extended hits", ext_dicts, ext_hits);
+
+    let comp_len = common::round_trip(&dir, "text.txt", &data);
+    assert!(comp_len < data.len() as u64 * 3 / 4, "{}", comp_len);
+}
+
+#[test]
+fn edge_sizes() {
+    let dir = common::test_dir("round-trip-edges");
+    let small = Options { chunk_size: 1 << 16, ..Options::default() };
+
+    // each way of coding the chunks, over the sizes at their edges
+    let cases = [
+        Options::default(),
+        Options { elem_bytes: 3, ..small.clone() },
+        Options { elem_bytes: 4, ..small.clone() },
+        Options { mode: Mode::RePair, ..small.clone() },
+        // the codes are built over blocks of the largest chunk size, whatever the chunk size of the options
+        Options { huffman: true, ..Options::default() },
+        Options { mode: Mode::RePair, huffman: true, ..Options::default() },
+        Options { rans: true, ..small.clone() },
+        Options { cost_select: true, ..small.clone() },
+        Options { optimal_parse: true, ..small.clone() },
+        Options { regions: true, ..small.clone() },
+        Options { bwt: true, ..small },
+    ];
+
+    // empty, shorter than an element, a few elements, and one byte more than a chunk
+    for options in cases.iter() {
+        for len in [0usize, 1, 2, 3, 4, 5, 64, 65, options.chunk_size + 1] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 7 + i % 5) as u8).collect();
+            common::round_trip_with(&dir, &format!("edge_{}.bin", len), &data, options);
+        }
+    }
+}
+
+#[test]
+fn wide_elements() {
+    let dir = common::test_dir("round-trip-wide");
+
+    for elem_bytes in [3usize, 4] {
+        let options = Options { elem_bytes, ..Options::default() };
+
+        // records of the element width, and text where elements keep shifting alignment
+        let records = common::records(300_000, elem_bytes);
+        let comp_len = common::round_trip_with(&dir, &format!("records_{}.bin", elem_bytes), &records, &options);
+        assert!(comp_len < records.len() as u64 / 2);
+
+        common::round_trip_with(&dir, &format!("text_{}.txt", elem_bytes), &common::text(200_000), &options);
+    }
+}
+
+#[test]
+fn re_pair_mode() {
+    let dir = common::test_dir("round-trip-re-pair");
+    let options = Options { mode: Mode::RePair, ..Options::default() };
+
+    let text = common::text(400_000);
+    let comp_len = common::round_trip_with(&dir, "text.txt", &text, &options);
+    assert!(comp_len < text.len() as u64 / 2);
+
+    let zeros = vec![0u8; 300_000];
+    let comp_len = common::round_trip_with(&dir, "zeros.bin", &zeros, &options);
+    assert!(comp_len < 1_000);
+
+    // the grammar is built over chunks of the chunk size of the options
+    let path = dir.join("text.txt");
+    std::fs::write(&path, &text).unwrap();
+    let layer_path = repair::compress_layer(&path, &text, 1 << 16).unwrap();
+    let layer = std::fs::read(&layer_path).unwrap();
+    std::fs::remove_file(&layer_path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut chunks = 0;
+    let mut pos = 0;
+
+    while pos < layer.len() {
+        pos += varint(&layer, &mut pos) as usize;
+        chunks += 1;
+    }
+
+    assert_eq!(chunks, text.len().div_ceil(1 << 16));
+}
+
+#[test]
+fn re_pair_grammars_at_the_edges() {
+    // noise has no pair frequent enough for a rule and a single byte no pair at all, while in a run of one byte
+    // each rule pairs the one before, until the sequence is a few symbols
+    for (data, has_rules) in [(noise(4096), false), (vec![7u8], false), (vec![0u8; 1 << 16], true)] {
+        let grammar = repair::build_grammar(&data);
+        assert_eq!(!grammar.rules.is_empty(), has_rules);
+        assert!(!has_rules || grammar.sequence.len() < 8, "{} symbols", grammar.sequence.len());
+
+        let mut decoded: Vec<u8> = vec![];
+        repair::decompress_chunk(&mut decoded, &repair::encode_grammar(&grammar)).unwrap();
+        assert!(decoded == data);
+    }
+}
+
+#[test]
+fn huffman_stage() {
+    let dir = common::test_dir("round-trip-huffman");
+    let text = common::text(400_000);
+
+    for mode in [Mode::Pairs, Mode::RePair] {
+        let coded = Options { mode, huffman: true, literals: false, ..Options::default() };
+        common::round_trip_with(&dir, "text.txt", &text, &coded);
+    }
+}
+
+#[test]
+fn huffman_blocks() {
+    // a block of text is coded, and the block of noise after it would grow, so it is stored as it is
+    let mut data = common::text(CHUNK_MAX_SIZE as usize);
+    data.extend(noise(100_000));
+
+    // each block is its length in 4 bytes and its method, 1 for coded and 0 for stored
+    let coded = huffman::encode(&data);
+    let second = utility::u8_vec_to_u64(&coded[..4]) as usize;
+    assert_eq!((coded[4], coded[second + 4]), (1, 0));
+    assert!(coded[second + 5..] == data[CHUNK_MAX_SIZE as usize..]);
+
+    // a single byte value still gets a code, and counts as skewed as the Fibonacci numbers have to be halved to
+    // keep the codes within 15 bits
+    let single = vec![5u8; 1000];
+    let (mut a, mut b) = (1, 1);
+    let skewed: Vec<u8> = (0..25u8)
+        .flat_map(|byte| {
+            (a, b) = (b, a + b);
+            std::iter::repeat_n(byte, a)
+        })
+        .collect();
+
+    for data in [&data[..], &single[..], &skewed[..], &[]] {
+        let coded = huffman::encode(data);
+        assert!(data.len() < 1000 || coded.len() < data.len());
+
+        let mut decoded: Vec<u8> = vec![];
+        huffman::decode(&coded, &mut decoded).unwrap();
+        assert!(decoded == data);
+    }
+}
+
+#[test]
+fn rans_tokens() {
+    let dir = common::test_dir("round-trip-rans");
+    let text = common::text(400_000);
+
+    common::round_trip_with(&dir, "text.txt", &text, &Options { rans: true, ..Options::default() });
+
+    let wide = Options { elem_bytes: 3, rans: true, ..Options::default() };
+    common::round_trip_with(&dir, "records.bin", &common::records(300_000, 3), &wide);
+}
+
+#[test]
+fn rans_models_rebuild() {
+    // the model starts out even and is rebuilt from the counts every 1024 symbols, so a byte repeated 1024
+    // times costs a byte each and the next 1024 repeats next to nothing
+    let first = rans::encode_literals(&[b'a'; 1024]).len();
+    let second = rans::encode_literals(&[b'a'; 2048]).len() - first;
+    assert!(first >= 1000 && second < 100, "{} and {} bytes", first, second);
+
+    // streams ending around the rebuilds, a byte the rebuilt model has only the smallest frequency for, and
+    // counts halved past their limit, read back in pieces that cross the rebuilds
+    let mut shifted = vec![b'a'; 1024];
+    shifted.extend([b'b'; 1024]);
+    let mut halved = vec![b'a'; 70_000];
+    halved.push(b'z');
+
+    for literals in [vec![b'a'; 1023], vec![b'a'; 1024], vec![b'a'; 1025], shifted, halved, noise(5000)] {
+        let coded = rans::encode_literals(&literals);
+        let mut reader = rans::LiteralReader::new(&coded).unwrap();
+        let mut decoded: Vec<u8> = vec![];
+
+        for piece in literals.chunks(700) {
+            reader.read(piece.len(), &mut decoded).unwrap();
+        }
+
+        assert!(decoded == literals, "{} bytes changed", literals.len());
+    }
+}
+
+#[test]
+fn cost_based_selection() {
+    let dir = common::test_dir("round-trip-cost-select");
+    let selected = Options { cost_select: true, ..Options::default() };
+
+    for (name, data) in [("text.txt", common::text(400_000)), ("records.bin", common::records(300_000, 2))] {
+        common::round_trip_with(&dir, name, &data, &selected);
+    }
+}
+
+#[test]
+fn cost_select_rounds() {
+    // 300 pairs in random order, the first of them the most common, so not all of them get one byte hits
+    let mut chunk: Vec<u8> = vec![];
+
+    for random in noise(90_000).chunks(2) {
+        let pair = (f64::from(u16::from_be_bytes([random[0], random[1]])) / 65536.0).powi(3) * 300.0;
+        chunk.extend([32 + (pair as usize / 20) as u8, 100 + (pair as usize % 20) as u8]);
+    }
+
+    let options = Options::default();
+
+    // the ranked dictionaries backwards, so the most common elements take the two byte hits
+    let backwards: Vec<Dictionary> = (0..2)
+        .map(|alignment| {
+            let ranked = compress::generate_ext_dict(&chunk, alignment, 2, options.min_occurrences);
+            let mut dict = Dictionary::with_elem_bytes(2);
+            dict.coverage = chunk.len() as u64;
+
+            for elem in ranked.elems.iter().rev() {
+                dict.push(DictElem::new(elem.bytes(), elem.occurance));
+            }
+
+            dict
+        })
+        .collect();
+
+    let mut encoded: Vec<u8> = vec![];
+    compress::compress_chunk(&mut backwards.clone(), &chunk, &mut encoded, &options).unwrap();
+
+    let refined = selection::refine(backwards.clone(), encoded.clone(), &chunk, &options).unwrap();
+    assert!(refined.encoded.len() < encoded.len(), "{} >= {}", refined.encoded.len(), encoded.len());
+    assert!(refined.estimated > 0.0);
+    assert!(refined.dicts[0].elems[0].occurance > refined.dicts[0].elems[VALUES_HALF].occurance);
+
+    // a round that does not beat the encoding it started from is dropped, with its dictionaries
+    let rejected = selection::refine(backwards.clone(), vec![0; 16], &chunk, &options).unwrap();
+    assert!(rejected.encoded == vec![0; 16]);
+    assert!(rejected.estimated == 0.0);
+
+    for (dict, kept) in backwards.iter().zip(rejected.dicts.iter()) {
+        assert!(dict.to_vec() == kept.to_vec());
+    }
+}
+
+#[test]
+fn optimal_parse() {
+    let dir = common::test_dir("round-trip-optimal");
+    let optimal = Options { optimal_parse: true, ..Options::default() };
+
+    for (name, data) in [("text.txt", common::text(400_000)), ("records.bin", common::records(300_000, 4))] {
+        common::round_trip_with(&dir, name, &data, &optimal);
+    }
+}
+
+#[test]
+fn cost_select_credits_hit_runs() {
+    // "xy" is hit once and then repeated by a hit run, among entries the chunk never hits
+    let chunk = b"xy".repeat(32);
+    let mut first = Dictionary::with_elem_bytes(2);
+
+    for byte in 0..50 {
+        first.push(DictElem::new(&[byte, 200], 1));
+    }
+
+    first.push(DictElem::new(b"xy", 32));
+    let dicts = vec![first, Dictionary::with_elem_bytes(2)];
+
+    // the single hit saves less than its entry, so only the repeats keep it. The encoding to beat is larger
+    // than any, so the first round is kept
+    let refined = selection::refine(dicts, vec![0; 1000], &chunk, &Options::default()).unwrap();
+    assert!(refined.dicts[0].to_vec() == b"xy", "{:?}", refined.dicts[0].to_vec());
+}
+
+#[test]
+fn optimal_parse_drops_single_hits() {
+    let mut first = Dictionary::with_elem_bytes(2);
+    first.push(DictElem::new(b"ab", 3));
+    first.push(DictElem::new(b"xy", 1));
+    let mut dicts = vec![first, Dictionary::with_elem_bytes(2)];
+
+    // "xy" is hit once by the first parse, which saves less than its entry takes, so the parse is run again
+    // without it
+    let (tokens, hits, misses) = optimal_parse::parse(&mut dicts, b"abababxy");
+
+    let hit = Token::Hit { dict: 0, index: 0 };
+    assert!(tokens == vec![hit.clone(), hit.clone(), hit, Token::Miss(6..8)], "{:?}", tokens);
+    assert!((hits, misses) == (6, 2));
+    assert!(dicts[0].to_vec() == b"ab");
+    assert!(dicts[0].get_elem(0).useage == 3);
+}
+
+#[test]
+fn optimal_parse_falls_back_to_greedy() {
+    // the optimal parse has no matches, so on repeated text it loses to the greedy parse, which is kept
+    let chunk = b"the quick brown fox jumps over the lazy dog. ".repeat(500);
+    let dicts: Vec<Dictionary> = (0..2)
+        .map(|alignment| {
+            let mut dict = compress::generate_dict(&chunk, alignment, 2, MIN_OCCATIONS);
+            dict.coverage = chunk.len() as u64;
+            dict
+        })
+        .collect();
+
+    let mut greedy: Vec<u8> = vec![];
+    compress::compress_chunk(&mut dicts.clone(), &chunk, &mut greedy, &Options::default()).unwrap();
+
+    let mut parsed: Vec<u8> = vec![];
+    let optimal = Options { optimal_parse: true, ..Options::default() };
+    compress::compress_chunk(&mut dicts.clone(), &chunk, &mut parsed, &optimal).unwrap();
+
+    assert!(parsed == greedy, "{} and {} bytes", parsed.len(), greedy.len());
+}
+
+#[test]
+fn region_dictionaries() {
+    let dir = common::test_dir("round-trip-regions");
+    let regions = Options { regions: true, ..Options::default() };
+
+    // segments too alike to be cut into chunks of their own, where the dictionaries of the whole chunk have to
+    // share their space. A single layer, as the layer above the two byte hits of the plain chunk saves more than
+    // one above the regions
+    let segments = alternating_values(780_000, 6);
+    let one_layer = Options { max_layers: 1, ..Options::default() };
+
+    let plain_len = common::round_trip_with(&dir, "segments.bin", &segments, &one_layer);
+    let regions_len = common::round_trip_with(&dir, "segments.bin", &segments, &Options { regions: true, ..one_layer });
+    assert!(regions_len < plain_len, "{} >= {}", regions_len, plain_len);
+
+    let wide = Options { elem_bytes: 4, ..regions };
+    common::round_trip_with(&dir, "records_4.bin", &common::records(300_000, 4), &wide);
+}
+
+#[test]
+fn region_chunks_at_the_edges() {
+    // the first dictionary is kept without any hits, so chunks of only misses still have one
+    for chunk in [vec![7], noise(4096)] {
+        let (dicts, hits, _, _) = region_chunk(&chunk);
+        assert!(dicts.len() == 1 && dicts[0].is_empty() && hits == 0);
+    }
+
+    // ten pairs the dictionaries of the whole chunk hold, so the four regions add none of theirs
+    let few: Vec<u8> = noise(4 << 16).iter().flat_map(|random| [b'a', b'0' + random % 10]).collect();
+    let (dicts, _, misses, _) = region_chunk(&few);
+    assert!(dicts.len() == 1 && misses == 0, "{} dictionaries", dicts.len());
+
+    // the second half of the values is only held by the dictionaries of its regions, which are numbered on
+    let halves = alternating_values(4 << 16, 2);
+    let (dicts, _, _, _) = region_chunk(&halves);
+    assert!(dicts.len() > 2 && dicts.len() < 10, "{} dictionaries", dicts.len());
+}
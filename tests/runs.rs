@@ -0,0 +1,66 @@
+use pcomp::comp_structs::options::Options;
+
+mod common;
+
+/// A disk image of 4 KiB blocks, most of them never written.
+fn sparse_image(blocks: usize) -> Vec<u8> {
+    let text = common::text(blocks * 1024);
+
+    (0..blocks)
+        .flat_map(|block| match block % 5 {
+            0 => text[block * 1024..block * 1024 + 4096].to_vec(),
+            _ => vec![0u8; 4096],
+        })
+        .collect()
+}
+
+#[test]
+fn sparse_images_collapse_in_one_layer() {
+    let dir = common::test_dir("runs-sparse");
+    let image = sparse_image(400);
+    let written = image.len() as u64 / 5;
+
+    // no help from matches, the zero blocks are left to the runs
+    let one_layer = Options { max_layers: 1, matches: false, ..Options::default() };
+    let with_runs = common::round_trip_with(&dir, "image.bin", &image, &one_layer);
+    assert!(with_runs < written, "{} >= {}", with_runs, written);
+
+    let without = Options { runs: false, ..one_layer };
+    assert!(common::round_trip_with(&dir, "image.bin", &image, &without) > image.len() as u64 / 2);
+}
+
+#[test]
+fn runs_round_trip_at_their_limits() {
+    let dir = common::test_dir("runs-limits");
+
+    for elem_bytes in [2usize, 3, 4] {
+        let options = Options { elem_bytes, matches: false, ..Options::default() };
+
+        // byte runs around the shortest and the longest with a count in the token, at every alignment
+        let mut data: Vec<u8> = vec![];
+
+        for len in [15usize, 16, 17, 30, 31, 32, 33, 100_000] {
+            for offset in 0..elem_bytes {
+                data.extend(common::text(offset + 7));
+                data.resize(data.len() + len, len as u8);
+            }
+        }
+
+        // elements repeated around the shortest hit runs, in common text so the elements are hits
+        for repeats in [2usize, 3, 4, 5, 18, 19, 20, 21, 5000] {
+            let elem: Vec<u8> = common::text(elem_bytes + 20)[20..].to_vec();
+            data.extend(common::text(50));
+
+            for _ in 0..repeats {
+                data.extend(&elem);
+            }
+        }
+
+        data.extend(common::text(2000));
+        data.resize(data.len() + 40, 0);
+        common::round_trip_with(&dir, &format!("limits_{}.bin", elem_bytes), &data, &options);
+
+        let with_matches = Options { matches: true, ..options };
+        common::round_trip_with(&dir, &format!("limits_{}.bin", elem_bytes), &data, &with_matches);
+    }
+}
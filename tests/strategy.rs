@@ -0,0 +1,115 @@
+use pcomp::comp_structs::chunk_method::{ChunkMethod, DELTA_DICTS, LITERALS, MATCHES, RUNS};
+use pcomp::comp_structs::{header::Header, options::Options};
+use pcomp::utility;
+use pcomp::{compress, decompress, strategy};
+
+mod common;
+
+/// Bytes from a generator, with `spread` distinct values at most.
+fn noise(len: usize, spread: u32, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((state >> 16) % spread) as u8
+        })
+        .collect()
+}
+
+/// Encodes one layer, and returns the method of each chunk with the data they decode to.
+fn chunk_methods(data: &[u8], options: &Options) -> (Vec<ChunkMethod>, Vec<u8>) {
+    let mut dicts = compress::generate_dict_collection(data, options);
+    let mut encoded: Vec<u8> = vec![];
+    compress::encode_layer(data, &mut dicts, &mut encoded, options, &[]).unwrap();
+
+    let header = Header::new(1, options.elem_bytes);
+    let mut previous = vec![];
+    let mut decoded: Vec<u8> = vec![];
+    let mut methods = vec![];
+    let mut pos = 0;
+
+    while pos < encoded.len() {
+        let method_byte = encoded[pos + utility::varint_bytes(&encoded[pos..])];
+        methods.push(ChunkMethod::from_u8(method_byte & !(DELTA_DICTS | MATCHES | RUNS | LITERALS)).unwrap());
+        pos += decompress::decompress_chunk(&mut decoded, &encoded[pos..], &header, &mut previous).unwrap();
+    }
+
+    (methods, decoded)
+}
+
+#[test]
+fn each_chunk_picks_its_own_encoding() {
+    let chunk_len = 1 << 16;
+
+    // text, random bytes, runs of random length with a few bytes between them, and bytes of a skewed
+    // distribution, then text again to show the dictionaries carry over the chunks without them
+    let mut data = common::text(chunk_len);
+    data.extend(noise(chunk_len, 256, 1));
+
+    let mut runs: Vec<u8> = vec![];
+    for (len, byte) in noise(chunk_len, 256, 2).chunks(2).map(|pair| (pair[0] as usize, pair[1])) {
+        runs.resize(runs.len() + len + 4, byte);
+        runs.extend(&noise(3, 256, len as u32));
+    }
+    runs.truncate(chunk_len);
+    data.extend(runs);
+
+    let skewed: Vec<u8> = noise(chunk_len, 64, 3).iter().zip(noise(chunk_len, 64, 4)).map(|(a, b)| a & b).collect();
+    data.extend(skewed);
+    data.extend(common::text(chunk_len));
+
+    use ChunkMethod::*;
+    let options = Options { chunk_size: chunk_len, ..Options::default() };
+    let (methods, decoded) = chunk_methods(&data, &options);
+    assert!(decoded == data);
+    assert!(methods[0] == Pairs && methods[methods.len() - 1] == Pairs, "{:?}", methods);
+    assert!(methods.contains(&Stored) && methods.contains(&Rle), "{:?}", methods);
+    assert!(!methods.contains(&EntropyBytes), "{:?}", methods);
+
+    // the optional codec takes at least the skewed bytes
+    let options = Options { entropy_chunks: true, ..options };
+    let (methods, decoded) = chunk_methods(&data, &options);
+    assert!(decoded == data);
+    assert!(methods.contains(&EntropyBytes), "{:?}", methods);
+}
+
+#[test]
+fn run_length_encoding_round_trips() {
+    let mut data: Vec<u8> = vec![];
+
+    for len in [0usize, 1, 3, 4, 5, 130, 131, 20_000] {
+        data.extend(noise(len % 7, 256, len as u32));
+        data.resize(data.len() + len, len as u8);
+    }
+
+    for data in [&data[..], &[], &[9], &[9; 4], &data[..data.len() - 3]] {
+        let mut decoded: Vec<u8> = vec![];
+        strategy::decode_rle(&mut decoded, &strategy::encode_rle(data)).unwrap();
+        assert!(decoded == data);
+    }
+
+    // a run without its byte, and more literals than follow
+    assert!(strategy::decode_rle(&mut vec![], &[0x03]).is_err());
+    assert!(strategy::decode_rle(&mut vec![], &[0x04, 1]).is_err());
+}
+
+#[test]
+fn mixed_files_round_trip() {
+    let dir = common::test_dir("strategy");
+
+    let mut data = common::text(100_000);
+    data.extend(noise(50_000, 256, 5));
+    data.resize(data.len() + 50_000, 7);
+    data.extend(noise(50_000, 16, 6));
+    data.extend(common::text(50_000));
+
+    common::round_trip(&dir, "mixed.bin", &data);
+
+    for level in 1..=9 {
+        let mut options = Options::with_level(level).unwrap();
+        options.entropy_chunks = true;
+        options.chunk_size = 1 << 15;
+        common::round_trip_with(&dir, "mixed.bin", &data, &options);
+    }
+}